@@ -1,19 +1,35 @@
 #!/bin/bash
 
+# usage: ./install.sh [daemon|timer]
+#   daemon: long running service updating the fan in a loop (default)
+#   timer:  oneshot service activated every minute by a systemd timer
+MODE="${1:-daemon}"
+
 cargo clean
 cargo build --release
 
 sudo cp ./target/release/rpi-temp-fan-pwm /usr/bin/rpi-temp-fan-pwm
 sudo chmod +x /usr/bin/rpi-temp-fan-pwm
 
-sudo cp ./src/pwm-fan.service /etc/systemd/system/pwm-fan.service
-# sudo cp ./src/pwm-fan.timer /etc/systemd/system/pwm-fan.timer
+if [ "$MODE" = "timer" ]; then
+    sudo systemctl disable --now pwm-fan.service 2>/dev/null
+
+    sudo cp ./src/pwm-fan-oneshot.service /etc/systemd/system/pwm-fan-oneshot.service
+    sudo cp ./src/pwm-fan.timer /etc/systemd/system/pwm-fan.timer
+
+    sudo systemctl daemon-reload
+    sudo systemctl enable pwm-fan.timer
+    sudo systemctl start pwm-fan.timer
+
+    sudo systemctl status pwm-fan.timer
+else
+    sudo systemctl disable --now pwm-fan.timer 2>/dev/null
 
-sudo systemctl daemon-reload
-# sudo systemctl enable pwm-fan.timer
-# sudo systemctl start pwm-fan.timer
+    sudo cp ./src/pwm-fan.service /etc/systemd/system/pwm-fan.service
 
-sudo systemctl start pwm-fan.service
+    sudo systemctl daemon-reload
+    sudo systemctl enable pwm-fan.service
+    sudo systemctl start pwm-fan.service
 
-# sudo systemctl status pwm-fan.timer
-sudo systemctl status pwm-fan.service
\ No newline at end of file
+    sudo systemctl status pwm-fan.service
+fi
\ No newline at end of file
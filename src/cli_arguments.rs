@@ -1,14 +1,30 @@
 // https://doc.rust-lang.org/book/ch07-02-defining-modules-to-control-scope-and-privacy.html
 
 pub mod cli_args {
-    use clap::Parser;
+    use crate::curve::fan_curve::{CurvePolicy, CurveProfile, FanCurve, SensorCurve};
+    use crate::emergency::emergency_action::EmergencyAction;
+    use crate::logger::app_logger::{parse_module_level, parse_size, LogFormat};
+    use crate::quiet::quiet_schedule::QuietProfile;
+    use crate::scheduler::tick_scheduler::{parse_duration, MIN_INTERVAL};
+    use crate::simulation::thermal_simulation::LoadProfile;
+    use crate::throttle::throttle_status::THROTTLED_FILE;
+    use crate::trip::trip_points;
+    use clap::{Args, Parser, Subcommand};
+    use log::{warn, LevelFilter};
     use mockall::predicate::*;
     use mockall::*;
     use std::fmt::Debug;
     use std::ops::RangeInclusive;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
 
     const PERCENTAGE: RangeInclusive<usize> = 1..=100;
 
+    pub const TEMP_FILE: &str = "/sys/class/thermal/thermal_zone0/temp";
+
+    /// Directory of the default control socket and state file of each PWM channel.
+    pub const RUN_DIR: &str = "/run/rpi-temp-fan-pwm";
+
     #[automock]
     pub trait CliArgsTrait {
         //fn valid(&self) -> bool;
@@ -19,13 +35,342 @@ pub mod cli_args {
 
         fn get_manual_speed(&self) -> Option<u8>;
 
+        fn get_manual_duration(&self) -> Option<Duration>;
+
         fn get_verbose(&self) -> clap_verbosity_flag::Verbosity;
 
         fn get_pwm_channel(&self) -> u8;
 
         fn get_pwm_freq(&self) -> f64;
 
-        fn get_sleep_secs(&self) -> u64;
+        fn get_interval(&self) -> Duration;
+
+        fn get_interval_jitter(&self) -> Duration;
+
+        fn is_adaptive_interval(&self) -> bool;
+
+        fn get_min_interval(&self) -> Duration;
+
+        fn get_max_interval(&self) -> Duration;
+
+        fn get_curves(&self) -> Vec<SensorCurve>;
+
+        fn get_curve_policy(&self) -> CurvePolicy;
+
+        fn get_profiles(&self) -> Vec<CurveProfile>;
+
+        fn get_profile(&self) -> Option<String>;
+
+        fn get_quiet_profiles(&self) -> Vec<QuietProfile>;
+
+        fn get_quiet_critical_temp(&self) -> u8;
+
+        fn get_critical_temp(&self) -> Option<u8>;
+
+        fn get_critical_actions(&self) -> Vec<EmergencyAction>;
+
+        fn get_critical_grace(&self) -> Duration;
+
+        fn get_critical_cooldown(&self) -> Duration;
+
+        fn is_critical_dry_run(&self) -> bool;
+
+        fn is_oneshot(&self) -> bool;
+
+        fn is_dry_run(&self) -> bool;
+
+        fn get_state_file(&self) -> PathBuf;
+        fn get_state_max_age(&self) -> Duration;
+
+        fn get_state_save_interval(&self) -> Duration;
+
+        fn get_rpm_file(&self) -> Option<PathBuf>;
+
+        fn get_throttled_file(&self) -> PathBuf;
+
+        fn get_throttle_boost(&self) -> Option<u8>;
+
+        fn get_metrics_listen(&self) -> Option<String>;
+
+        fn get_metrics_textfile(&self) -> Option<PathBuf>;
+
+        fn get_mqtt(&self) -> MqttArgs;
+
+        fn get_log(&self) -> LogArgs;
+
+        fn get_history(&self) -> HistoryArgs;
+
+        fn get_control_socket(&self) -> Option<PathBuf>;
+
+        fn get_control_socket_group(&self) -> Option<String>;
+
+        fn get_command(&self) -> Option<Command>;
+    }
+
+    /// MQTT publishing with Home Assistant discovery, requires the mqtt feature.
+    #[derive(Args, Debug, Clone, PartialEq)]
+    pub struct MqttArgs {
+        /// MQTT broker host, enables the MQTT publishing
+        #[arg(long)]
+        mqtt_host: Option<String>,
+
+        /// MQTT broker port
+        #[arg(long, default_value_t = 1883)]
+        mqtt_port: u16,
+
+        #[arg(long, requires = "mqtt_password")]
+        mqtt_user: Option<String>,
+
+        #[arg(long, requires = "mqtt_user")]
+        mqtt_password: Option<String>,
+
+        /// Identifier of this device, used in topics and Home Assistant unique ids.
+        /// Default: host name
+        #[arg(long)]
+        mqtt_node_id: Option<String>,
+
+        /// Topic prefix for state, availability and command topics.
+        /// Default: rpi-temp-fan-pwm/<node id>
+        #[arg(long)]
+        mqtt_base_topic: Option<String>,
+
+        /// Home Assistant discovery prefix
+        #[arg(long, default_value = "homeassistant")]
+        mqtt_discovery_prefix: String,
+    }
+
+    impl MqttArgs {
+        #[allow(dead_code)]
+        pub fn new(
+            mqtt_host: Option<String>,
+            mqtt_port: u16,
+            mqtt_node_id: Option<String>,
+            mqtt_base_topic: Option<String>,
+        ) -> Self {
+            MqttArgs {
+                mqtt_host,
+                mqtt_port,
+                mqtt_user: None,
+                mqtt_password: None,
+                mqtt_node_id,
+                mqtt_base_topic,
+                mqtt_discovery_prefix: "homeassistant".to_string(),
+            }
+        }
+
+        pub fn get_host(&self) -> Option<&str> {
+            self.mqtt_host.as_deref()
+        }
+
+        #[cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+        pub fn get_port(&self) -> u16 {
+            self.mqtt_port
+        }
+
+        #[cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+        pub fn get_credentials(&self) -> Option<(&str, &str)> {
+            self.mqtt_user.as_deref().zip(self.mqtt_password.as_deref())
+        }
+
+        #[cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+        pub fn get_node_id(&self) -> String {
+            self.mqtt_node_id.clone().unwrap_or_else(|| {
+                std::fs::read_to_string("/proc/sys/kernel/hostname")
+                    .map(|h| h.trim().to_string())
+                    .unwrap_or_else(|_| "rpi".to_string())
+            })
+        }
+
+        #[cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+        pub fn get_base_topic(&self) -> String {
+            self.mqtt_base_topic
+                .clone()
+                .unwrap_or_else(|| format!("rpi-temp-fan-pwm/{}", self.get_node_id()))
+        }
+
+        #[cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+        pub fn get_discovery_prefix(&self) -> &str {
+            &self.mqtt_discovery_prefix
+        }
+    }
+
+    /// Log file and format, the console or journal output is always kept.
+    #[derive(Args, Debug, Clone, PartialEq)]
+    pub struct LogArgs {
+        /// Also write the logs to this file, rotated by size
+        #[arg(long)]
+        log_file: Option<PathBuf>,
+
+        /// Size rotating the log file, e.g. 512K or 10M
+        #[arg(long, default_value = "10M", value_parser = parse_size)]
+        log_file_size: u64,
+
+        /// Rotated log files kept, as LOG_FILE.1 to LOG_FILE.N
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+        log_file_count: u32,
+
+        /// Format of the console and file logs
+        #[arg(long, value_enum, default_value_t = LogFormat::Human)]
+        log_format: LogFormat,
+
+        /// Level of a module, e.g. pwm=debug or socket=warn, can be repeated
+        #[arg(long = "log-level", value_name = "MODULE=LEVEL", value_parser = parse_module_level)]
+        log_levels: Vec<(String, LevelFilter)>,
+    }
+
+    impl LogArgs {
+        pub fn get_file(&self) -> Option<&Path> {
+            self.log_file.as_deref()
+        }
+
+        pub fn get_file_size(&self) -> u64 {
+            self.log_file_size
+        }
+
+        pub fn get_file_count(&self) -> u32 {
+            self.log_file_count
+        }
+
+        pub fn get_format(&self) -> LogFormat {
+            self.log_format
+        }
+
+        pub fn get_levels(&self) -> &[(String, LevelFilter)] {
+            &self.log_levels
+        }
+    }
+
+    /// CSV history of the updates, for offline analysis and replay.
+    #[derive(Args, Debug, Clone, PartialEq)]
+    pub struct HistoryArgs {
+        /// Append temperatures, duty cycle, RPM and mode of each update to this CSV file
+        #[arg(long)]
+        history_file: Option<PathBuf>,
+
+        /// Size rotating the history file, e.g. 512K or 10M
+        #[arg(long, default_value = "10M", value_parser = parse_size)]
+        history_file_size: u64,
+
+        /// Rotated history files kept, as HISTORY_FILE.1 to HISTORY_FILE.N
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+        history_file_count: u32,
+    }
+
+    impl HistoryArgs {
+        pub fn get_file(&self) -> Option<&Path> {
+            self.history_file.as_deref()
+        }
+
+        pub fn get_file_size(&self) -> u64 {
+            self.history_file_size
+        }
+
+        pub fn get_file_count(&self) -> u32 {
+            self.history_file_count
+        }
+    }
+
+    #[derive(Subcommand, Debug, Clone, PartialEq)]
+    pub enum Command {
+        /// Read the temperature, apply the duty cycle and exit leaving the PWM enabled.
+        /// Meant to be called from a systemd timer.
+        Oneshot,
+        /// Print the state of the running daemon, read from the control socket
+        Status {
+            /// Print the raw JSON status, for scripting
+            #[arg(long)]
+            json: bool,
+        },
+        /// Feed a history file through the configured curves, without touching the fan,
+        /// and print the resulting duty cycle series as CSV
+        Replay {
+            /// History file written with --history-file
+            input: PathBuf,
+
+            /// Write the series to this file instead of the standard output
+            #[arg(short, long)]
+            output: Option<PathBuf>,
+        },
+        /// Print the effective fan curve as a terminal chart and a table of the duty cycle
+        /// at each degree, every sensor reading the same temperature
+        ShowCurve {
+            /// Lowest temperature shown.
+            /// Default: 10°C below the first step
+            #[arg(long)]
+            from: Option<u8>,
+
+            /// Highest temperature shown.
+            /// Default: 10°C above the last step
+            #[arg(long)]
+            to: Option<u8>,
+        },
+        /// Write the effective fan curve as an SVG chart, followed by the temperature and
+        /// duty cycle over time of a recorded history when given
+        ExportPlot {
+            /// SVG file to write
+            output: PathBuf,
+
+            /// History file written with --history-file, charted below the curve
+            #[arg(long)]
+            history: Option<PathBuf>,
+
+            /// Lowest temperature of the curve.
+            /// Default: 10°C below the first step
+            #[arg(long)]
+            from: Option<u8>,
+
+            /// Highest temperature of the curve.
+            /// Default: 10°C above the last step
+            #[arg(long)]
+            to: Option<u8>,
+        },
+        /// Live dashboard of the temperatures, duty cycle, fan speed and curve, attached to
+        /// the running daemon through the control socket. Needs the tui feature
+        Monitor {
+            /// Read the sensors and run the configured curves in the monitor, without
+            /// driving the fan
+            #[arg(long)]
+            standalone: bool,
+
+            /// Refresh period of the screen
+            #[arg(long, default_value = "1s", value_parser = parse_duration)]
+            refresh: Duration,
+
+            /// Duration of the manual speeds set from the keyboard
+            #[arg(long, default_value = "10m", value_parser = parse_duration)]
+            manual_duration: Duration,
+        },
+        /// Run the control loop against a first-order thermal model of the SoC, without
+        /// touching the fan, and print the temperature and duty cycle series as CSV
+        Simulate {
+            /// Simulated time
+            #[arg(long, default_value = "10m", value_parser = parse_duration)]
+            duration: Duration,
+
+            /// Heat input as DURATION:WATTS segments, the last one lasts until the end
+            #[arg(long, default_value = "1m:3,5m:8,4m:3")]
+            load: LoadProfile,
+
+            /// Ambient temperature in Celsius, the starting temperature of the model
+            #[arg(long, default_value_t = 25.0)]
+            ambient: f64,
+
+            /// Heat capacity of the SoC and heatsink, in J/K
+            #[arg(long, default_value_t = 20.0, value_parser = positive_number)]
+            heat_capacity: f64,
+
+            /// Cooling of the heatsink without airflow, in W/K
+            #[arg(long, default_value_t = 0.1, value_parser = non_negative_number)]
+            passive_conductance: f64,
+
+            /// Cooling added by the fan at full speed, in W/K
+            #[arg(long, default_value_t = 0.3, value_parser = non_negative_number)]
+            fan_conductance: f64,
+
+            /// Write the series to this file instead of the standard output
+            #[arg(short, long)]
+            output: Option<PathBuf>,
+        },
     }
 
     #[derive(Parser, Debug)]
@@ -42,6 +387,10 @@ pub mod cli_args {
         #[arg(short = 'u', long, value_parser = percentage_in_range)]
         manual_speed: Option<u8>,
 
+        /// Revert the manual speed to automatic control after this duration, e.g. 10m
+        #[arg(long, requires = "manual_speed", value_parser = parse_duration)]
+        manual_duration: Option<Duration>,
+
         #[command(flatten)]
         verbose: clap_verbosity_flag::Verbosity,
 
@@ -55,35 +404,269 @@ pub mod cli_args {
         #[arg(short = 'f', long, default_value_t = 2.0)]
         pwm_freq: f64,
 
-        /// Set the sleep period between pwm updates
-        #[arg(short = 'e', long, default_value_t = 0)]
-        sleep_secs: u64,
+        /// Period between pwm updates, e.g. 500ms, 2s, 1m (minimum 100ms)
+        #[arg(short = 'e', long, alias = "sleep-secs", default_value = "1s", value_parser = interval_duration)]
+        interval: Duration,
+
+        /// Random delay up to this duration added to each update, e.g. 200ms
+        #[arg(long, default_value = "0s", value_parser = parse_duration)]
+        interval_jitter: Duration,
+
+        /// Adapt the period between updates to the temperature trend, from min_interval
+        /// when it changes fast or is close to a curve step, up to max_interval when stable
+        #[arg(long)]
+        adaptive_interval: bool,
+
+        /// Shortest period of the adaptive interval
+        #[arg(long, default_value = "500ms", value_parser = interval_duration)]
+        min_interval: Duration,
+
+        /// Longest period of the adaptive interval
+        #[arg(long, default_value = "10s", value_parser = interval_duration)]
+        max_interval: Duration,
+
+        /// Curve bound to a sensor file, as SENSOR:TEMP_STEPS:SPEED_STEPS[:WEIGHT].
+        /// Can be repeated to drive the fan from several sensors.
+        /// Default: temp_step and speed_step applied to thermal_zone0.
+        #[arg(short = 'r', long = "curve")]
+        curves: Vec<SensorCurve>,
+
+        /// How the speeds of several curves are combined
+        #[arg(short = 'p', long, value_enum, default_value_t = CurvePolicy::Max)]
+        curve_policy: CurvePolicy,
+
+        /// Default curve derived from the kernel trip points of thermal_zone0, reaching full
+        /// speed a few degrees before the passive trip. Ignored with --curve.
+        #[arg(long)]
+        trip_point_curve: bool,
+
+        /// Curve read from the trip points once, see `resolve_trip_point_curve`
+        #[arg(skip)]
+        trip_point_default: Option<FanCurve>,
+
+        /// Named curve applied to the first sensor: silent, balanced, performance or
+        /// one added with --define-profile
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Add or replace a named curve, as NAME=TEMP_STEPS:SPEED_STEPS, e.g. night=60,75:0,100 .
+        /// Can be repeated.
+        #[arg(long = "define-profile")]
+        profile_definitions: Vec<CurveProfile>,
+
+        /// Local time window capping the fan speed, as START-END,MAX_SPEED[,TEMP_OFFSET],
+        /// e.g. 22:00-07:00,40,5 caps at 40% and reads temperatures 5°C lower.
+        /// Can be repeated, the first matching window applies.
+        #[arg(long = "quiet-profile")]
+        quiet_profiles: Vec<QuietProfile>,
+
+        /// Temperature at which the quiet profile cap is ignored
+        #[arg(long, default_value_t = 75)]
+        quiet_critical_temp: u8,
+
+        /// Temperature running the --critical-action actions
+        #[arg(long, requires = "critical_actions")]
+        critical_temp: Option<u8>,
+
+        /// Action at the critical temperature: hook:COMMAND, poweroff or cpufreq:KHZ .
+        /// Can be repeated.
+        #[arg(long = "critical-action", requires = "critical_temp")]
+        critical_actions: Vec<EmergencyAction>,
+
+        /// How long the temperature must stay critical before running the actions
+        #[arg(long, default_value = "10s", value_parser = parse_duration)]
+        critical_grace: Duration,
+
+        /// Minimum period between two runs of the actions
+        #[arg(long, default_value = "5m", value_parser = parse_duration)]
+        critical_cooldown: Duration,
+
+        /// Log the critical actions instead of running them
+        #[arg(long)]
+        critical_dry_run: bool,
+
+        /// Same as the oneshot subcommand
+        #[arg(long)]
+        once: bool,
+
+        /// Run the control loop without driving the fan, also off a Raspberry Pi
+        #[arg(long)]
+        dry_run: bool,
+
+        /// File where the controller state is kept between runs [default:
+        /// /run/rpi-temp-fan-pwm/state-pwmN of the PWM channel]
+        #[arg(long)]
+        state_file: Option<PathBuf>,
+
+        /// Ignore a state file saved longer ago than this
+        #[arg(long, default_value = "10m", value_parser = parse_duration)]
+        state_max_age: Duration,
+
+        /// Period between two saves of the controller state while running
+        #[arg(long, default_value = "1m", value_parser = parse_duration)]
+        state_save_interval: Duration,
+
+        /// Fan tachometer file reporting RPM, e.g. /sys/class/hwmon/hwmon2/fan1_input
+        #[arg(long)]
+        rpm_file: Option<PathBuf>,
+
+        /// Firmware throttling flags, read at each update. vcgencmd is used on a Raspberry Pi
+        /// without it
+        #[arg(long, default_value = THROTTLED_FILE)]
+        throttled_file: PathBuf,
+
+        /// Minimum fan speed in percentage while the firmware soft temperature limit is active
+        #[arg(long, value_parser = percentage_in_range)]
+        throttle_boost: Option<u8>,
+
+        /// Address of the Prometheus /metrics endpoint, e.g. 0.0.0.0:9101 .
+        /// Requires the prometheus feature.
+        #[arg(long)]
+        metrics_listen: Option<String>,
+
+        /// node_exporter textfile collector file written at each update,
+        /// e.g. /var/lib/node_exporter/textfile_collector/rpi_fan.prom
+        #[arg(long)]
+        metrics_textfile: Option<PathBuf>,
+
+        #[command(flatten)]
+        mqtt: MqttArgs,
+
+        #[command(flatten)]
+        log: LogArgs,
+
+        #[command(flatten)]
+        history: HistoryArgs,
+
+        /// Unix socket accepting line-delimited JSON commands while running.
+        /// Default: /run/rpi-temp-fan-pwm/control-pwm<PWM_CHANNEL>.sock
+        #[arg(long, global = true)]
+        control_socket: Option<PathBuf>,
+
+        /// Don't create the control socket
+        #[arg(long)]
+        no_control_socket: bool,
+
+        /// Group, by name or id, allowed to use the control socket.
+        /// Default: only the daemon user
+        #[arg(long)]
+        control_socket_group: Option<String>,
+
+        #[command(subcommand)]
+        command: Option<Command>,
     }
 
     impl CliArgs {
-        #[allow(dead_code)]
+        #[allow(dead_code, clippy::too_many_arguments)]
         pub fn new(
             temp_step: Vec<u8>,
             speed_step: Vec<u8>,
             manual_speed: Option<u8>,
+            manual_duration: Option<Duration>,
             verbose: clap_verbosity_flag::Verbosity,
             pwm_channel: u8,
             pwm_freq: f64,
-            sleep_secs: u64,
+            interval: Duration,
+            interval_jitter: Duration,
+            adaptive_interval: bool,
+            min_interval: Duration,
+            max_interval: Duration,
+            curves: Vec<SensorCurve>,
+            curve_policy: CurvePolicy,
+            trip_point_curve: bool,
+            profile: Option<String>,
+            profile_definitions: Vec<CurveProfile>,
+            quiet_profiles: Vec<QuietProfile>,
+            quiet_critical_temp: u8,
+            critical_temp: Option<u8>,
+            critical_actions: Vec<EmergencyAction>,
+            critical_grace: Duration,
+            critical_cooldown: Duration,
+            critical_dry_run: bool,
+            once: bool,
+            dry_run: bool,
+            state_file: Option<PathBuf>,
+            state_max_age: Duration,
+            state_save_interval: Duration,
+            rpm_file: Option<PathBuf>,
+            throttled_file: PathBuf,
+            throttle_boost: Option<u8>,
+            metrics_listen: Option<String>,
+            metrics_textfile: Option<PathBuf>,
+            mqtt: MqttArgs,
+            log: LogArgs,
+            history: HistoryArgs,
+            control_socket: Option<PathBuf>,
+            no_control_socket: bool,
+            control_socket_group: Option<String>,
+            command: Option<Command>,
         ) -> Self {
             CliArgs {
                 temp_step,
                 speed_step,
                 manual_speed,
+                manual_duration,
                 verbose,
                 pwm_channel,
                 pwm_freq,
-                sleep_secs,
+                interval,
+                interval_jitter,
+                adaptive_interval,
+                min_interval,
+                max_interval,
+                curves,
+                curve_policy,
+                trip_point_curve,
+                trip_point_default: None,
+                profile,
+                profile_definitions,
+                quiet_profiles,
+                quiet_critical_temp,
+                critical_temp,
+                critical_actions,
+                critical_grace,
+                critical_cooldown,
+                critical_dry_run,
+                once,
+                dry_run,
+                state_file,
+                state_max_age,
+                state_save_interval,
+                rpm_file,
+                throttled_file,
+                throttle_boost,
+                metrics_listen,
+                metrics_textfile,
+                mqtt,
+                log,
+                history,
+                control_socket,
+                no_control_socket,
+                control_socket_group,
+                command,
+            }
+        }
+
+        /// Read the trip points for --trip-point-curve, once the logger is configured.
+        pub fn resolve_trip_point_curve(&mut self) {
+            if self.trip_point_curve && self.curves.is_empty() {
+                self.trip_point_default = trip_point_default_curve();
             }
         }
 
         pub fn valid(&self) -> bool {
             self.temp_step.len() == self.speed_step.len()
+                && self.get_curves().iter().all(|sc| sc.get_curve().valid())
+        }
+
+        pub fn valid_intervals(&self) -> bool {
+            self.min_interval <= self.max_interval
+        }
+
+        pub fn valid_profile(&self) -> bool {
+            self.profile
+                .as_ref()
+                .is_none_or(|name| self.get_profiles().iter().any(|p| p.get_name() == name))
         }
     }
 
@@ -100,6 +683,10 @@ pub mod cli_args {
             self.manual_speed
         }
 
+        fn get_manual_duration(&self) -> Option<Duration> {
+            self.manual_duration
+        }
+
         fn get_verbose(&self) -> clap_verbosity_flag::Verbosity {
             self.verbose
         }
@@ -112,11 +699,203 @@ pub mod cli_args {
             self.pwm_freq
         }
 
-        fn get_sleep_secs(&self) -> u64 {
-            self.sleep_secs
+        fn get_interval(&self) -> Duration {
+            self.interval
+        }
+
+        fn get_interval_jitter(&self) -> Duration {
+            self.interval_jitter
+        }
+
+        fn is_adaptive_interval(&self) -> bool {
+            self.adaptive_interval
+        }
+
+        fn get_min_interval(&self) -> Duration {
+            self.min_interval
+        }
+
+        fn get_max_interval(&self) -> Duration {
+            self.max_interval
+        }
+
+        fn get_curves(&self) -> Vec<SensorCurve> {
+            if self.curves.is_empty() {
+                vec![SensorCurve::new(
+                    TEMP_FILE.to_string(),
+                    self.trip_point_default.clone().unwrap_or_else(|| {
+                        FanCurve::new(self.get_temp_step(), self.get_speed_step())
+                    }),
+                    1.0,
+                )]
+            } else {
+                self.curves.clone()
+            }
+        }
+
+        fn get_curve_policy(&self) -> CurvePolicy {
+            self.curve_policy
+        }
+
+        fn get_profiles(&self) -> Vec<CurveProfile> {
+            let mut profiles = CurveProfile::builtin();
+            for definition in &self.profile_definitions {
+                match profiles
+                    .iter_mut()
+                    .find(|p| p.get_name() == definition.get_name())
+                {
+                    Some(profile) => *profile = definition.clone(),
+                    None => profiles.push(definition.clone()),
+                }
+            }
+            profiles
+        }
+
+        fn get_profile(&self) -> Option<String> {
+            self.profile.clone()
+        }
+
+        fn get_quiet_profiles(&self) -> Vec<QuietProfile> {
+            self.quiet_profiles.clone()
+        }
+
+        fn get_quiet_critical_temp(&self) -> u8 {
+            self.quiet_critical_temp
+        }
+
+        fn get_critical_temp(&self) -> Option<u8> {
+            self.critical_temp
+        }
+
+        fn get_critical_actions(&self) -> Vec<EmergencyAction> {
+            self.critical_actions.clone()
+        }
+
+        fn get_critical_grace(&self) -> Duration {
+            self.critical_grace
+        }
+
+        fn get_critical_cooldown(&self) -> Duration {
+            self.critical_cooldown
+        }
+
+        fn is_critical_dry_run(&self) -> bool {
+            self.critical_dry_run
+        }
+
+        fn is_oneshot(&self) -> bool {
+            self.once || self.command == Some(Command::Oneshot)
+        }
+
+        fn is_dry_run(&self) -> bool {
+            self.dry_run
+        }
+
+        fn get_state_file(&self) -> PathBuf {
+            self.state_file.clone().unwrap_or_else(|| {
+                Path::new(RUN_DIR).join(format!("state-pwm{}", self.pwm_channel))
+            })
+        }
+
+        fn get_state_max_age(&self) -> Duration {
+            self.state_max_age
+        }
+
+        fn get_state_save_interval(&self) -> Duration {
+            self.state_save_interval
+        }
+
+        fn get_rpm_file(&self) -> Option<PathBuf> {
+            self.rpm_file.clone()
+        }
+
+        fn get_throttled_file(&self) -> PathBuf {
+            self.throttled_file.clone()
+        }
+
+        fn get_throttle_boost(&self) -> Option<u8> {
+            self.throttle_boost
+        }
+
+        fn get_metrics_listen(&self) -> Option<String> {
+            self.metrics_listen.clone()
+        }
+
+        fn get_metrics_textfile(&self) -> Option<PathBuf> {
+            self.metrics_textfile.clone()
+        }
+
+        fn get_mqtt(&self) -> MqttArgs {
+            self.mqtt.clone()
+        }
+
+        fn get_log(&self) -> LogArgs {
+            self.log.clone()
+        }
+
+        fn get_history(&self) -> HistoryArgs {
+            self.history.clone()
+        }
+
+        fn get_control_socket(&self) -> Option<PathBuf> {
+            // one socket per channel, so that each fan can have its own daemon
+            (!self.no_control_socket).then(|| {
+                self.control_socket.clone().unwrap_or_else(|| {
+                    Path::new(RUN_DIR).join(format!("control-pwm{}.sock", self.pwm_channel))
+                })
+            })
+        }
+
+        fn get_control_socket_group(&self) -> Option<String> {
+            self.control_socket_group.clone()
+        }
+
+        fn get_command(&self) -> Option<Command> {
+            self.command.clone()
         }
     }
 
+    // curve from the thermal_zone0 trip points, none when they can't be read
+    fn trip_point_default_curve() -> Option<FanCurve> {
+        let zone_dir = Path::new(TEMP_FILE).parent()?;
+        match trip_points::read(zone_dir) {
+            Ok(trips) => {
+                let curve = trip_points::default_curve(&trips);
+                if curve.is_none() {
+                    warn!("No usable trip point in {}", zone_dir.display());
+                }
+                curve
+            }
+            Err(e) => {
+                warn!("Error reading trip points of {}: {}", zone_dir.display(), e);
+                None
+            }
+        }
+    }
+
+    fn interval_duration(s: &str) -> Result<Duration, String> {
+        let interval = parse_duration(s)?;
+        if interval < MIN_INTERVAL {
+            Err(format!("Interval must be at least {MIN_INTERVAL:?}"))
+        } else {
+            Ok(interval)
+        }
+    }
+
+    fn positive_number(s: &str) -> Result<f64, String> {
+        s.parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite() && *v > 0.0)
+            .ok_or_else(|| format!("`{s}` isn't a positive number"))
+    }
+
+    fn non_negative_number(s: &str) -> Result<f64, String> {
+        s.parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite() && *v >= 0.0)
+            .ok_or_else(|| format!("`{s}` isn't a non-negative number"))
+    }
+
     fn percentage_in_range(s: &str) -> Result<u8, String> {
         let port: usize = s
             .parse()
@@ -127,4 +906,65 @@ pub mod cli_args {
             Err("Value not in percentage range 0-100".to_string())
         }
     }
+
+    /// Command line of the tests, the settings read by the controller as plain values.
+    #[cfg(test)]
+    #[derive(Debug, Clone)]
+    pub struct MockArgs {
+        pub curves: Vec<SensorCurve>,
+        pub manual_speed: Option<u8>,
+        pub manual_duration: Option<Duration>,
+        pub quiet_profiles: Vec<QuietProfile>,
+        pub throttle_boost: Option<u8>,
+    }
+
+    #[cfg(test)]
+    impl Default for MockArgs {
+        fn default() -> Self {
+            MockArgs {
+                curves: vec![SensorCurve::new(
+                    "cpu".to_string(),
+                    FanCurve::new(vec![50, 70], vec![20, 100]),
+                    1.0,
+                )],
+                manual_speed: None,
+                manual_duration: None,
+                quiet_profiles: Vec::new(),
+                throttle_boost: None,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    impl MockArgs {
+        /// Mock returning these settings, other expectations can be added.
+        pub fn mock(self) -> MockCliArgsTrait {
+            let mut cli_mock = MockCliArgsTrait::new();
+            cli_mock
+                .expect_get_curves()
+                .returning(move || self.curves.clone());
+            cli_mock
+                .expect_get_curve_policy()
+                .returning(|| CurvePolicy::Max);
+            cli_mock
+                .expect_get_profiles()
+                .returning(CurveProfile::builtin);
+            cli_mock.expect_get_profile().returning(|| None);
+            cli_mock
+                .expect_get_manual_speed()
+                .returning(move || self.manual_speed);
+            cli_mock
+                .expect_get_manual_duration()
+                .returning(move || self.manual_duration);
+            cli_mock.expect_get_quiet_critical_temp().returning(|| 75);
+            cli_mock
+                .expect_get_quiet_profiles()
+                .returning(move || self.quiet_profiles.clone());
+            cli_mock
+                .expect_get_throttle_boost()
+                .returning(move || self.throttle_boost);
+            cli_mock.expect_get_pwm_freq().returning(|| 2.0);
+            cli_mock
+        }
+    }
 }
@@ -0,0 +1,21 @@
+pub mod control_command {
+    use crate::curve::fan_curve::SensorCurve;
+    use std::time::Duration;
+
+    /// Command sent to the control loop by a remote interface, applied between two updates.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum ControlCommand {
+        /// Force the fan speed, in percentage
+        SetManual(u8),
+        /// Force the fan speed, in percentage, for a limited time
+        SetManualFor(u8, Duration),
+        /// Back to the speed computed by the curves
+        ClearManual,
+        /// Replace the curve of the same sensor
+        SetCurve(SensorCurve),
+        /// Apply a named curve to the first sensor
+        SetProfile(String),
+        /// Back to the command line settings
+        Reload,
+    }
+}
@@ -0,0 +1,573 @@
+pub mod fan_controller {
+    use crate::cli_arguments::cli_args::CliArgsTrait;
+    use crate::control::control_command::ControlCommand;
+    use crate::curve::fan_curve::{self, CurvePolicy, CurveProfile, SensorCurve};
+    use crate::pwm::pwm_manager::PwmManagerTrait;
+    use crate::quiet::quiet_schedule::QuietProfile;
+    use crate::throttle::throttle_status::ThrottleFlags;
+    use log::{debug, info, warn};
+    use std::error::Error;
+    use std::fmt;
+    use std::str::FromStr;
+    use std::time::{Duration, Instant};
+
+    /// Where the applied duty cycle comes from.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum FanMode {
+        /// Computed by the curves
+        #[default]
+        Auto,
+        /// Computed by the curves, capped by a quiet profile
+        Quiet,
+        /// Forced from the command line or a remote interface
+        Manual,
+        /// Full speed, the sensors can't be read
+        Failsafe,
+    }
+
+    impl fmt::Display for FanMode {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                FanMode::Auto => write!(f, "auto"),
+                FanMode::Quiet => write!(f, "quiet"),
+                FanMode::Manual => write!(f, "manual"),
+                FanMode::Failsafe => write!(f, "failsafe"),
+            }
+        }
+    }
+
+    impl FromStr for FanMode {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "auto" => Ok(FanMode::Auto),
+                "quiet" => Ok(FanMode::Quiet),
+                "manual" => Ok(FanMode::Manual),
+                "failsafe" => Ok(FanMode::Failsafe),
+                _ => Err(format!("`{s}` isn't one of auto, quiet, manual, failsafe")),
+            }
+        }
+    }
+
+    /// Forced speed, reverting to automatic control at `until` when set.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct ManualOverride {
+        speed: u8,
+        until: Option<Instant>,
+    }
+
+    impl ManualOverride {
+        fn new(speed: u8, duration: Option<Duration>) -> Self {
+            ManualOverride {
+                speed,
+                until: duration.map(|d| Instant::now() + d),
+            }
+        }
+
+        fn expired(&self, now: Instant) -> bool {
+            self.until.is_some_and(|until| now >= until)
+        }
+    }
+
+    /// Settings changed at runtime kept across runs, expiries as time left.
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct ControllerSettings {
+        /// Time left of the timed command line override, zero once expired
+        pub manual_speed_remaining: Option<Duration>,
+        /// Speed and time left of the override set by a control command
+        pub manual_override: Option<(u8, Option<Duration>)>,
+        pub profile: Option<String>,
+    }
+
+    /// Readings of one iteration of the control loop.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct ControlInput<'a> {
+        /// Temperatures in curves order, the last readings after a sensor error
+        pub temps: &'a [u8],
+        pub sensor_error: bool,
+        pub quiet: Option<&'a QuietProfile>,
+        pub throttle_flags: Option<ThrottleFlags>,
+    }
+
+    /// Outcome of one iteration of the control loop.
+    #[derive(Debug)]
+    pub struct ControlStep {
+        /// Duty of the curves, quiet profile or manual override
+        pub target: f64,
+        /// Duty sent to the fan, with the throttle boost and the failsafe
+        pub duty: f64,
+        pub mode: FanMode,
+        /// Error of the PWM backend, the fan keeps its previous duty
+        pub error: Option<Box<dyn Error>>,
+    }
+
+    impl ControlStep {
+        pub fn get_applied(&self) -> Option<f64> {
+            self.error.is_none().then_some(self.duty)
+        }
+    }
+
+    /// Settings of the control loop, starting from the command line and changed at
+    /// runtime by the control commands.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct FanController {
+        curves: Vec<SensorCurve>,
+        curve_policy: CurvePolicy,
+        profiles: Vec<CurveProfile>,
+        profile: Option<String>,
+        quiet_critical_temp: u8,
+        throttle_boost: Option<u8>,
+        pwm_freq: f64,
+        manual_speed: Option<ManualOverride>,
+        manual_override: Option<ManualOverride>,
+    }
+
+    impl FanController {
+        pub fn new(cli_args: &impl CliArgsTrait) -> Self {
+            let controller = Self::from_cli_args(cli_args);
+            if let Some(manual) = controller.manual_speed {
+                match cli_args.get_manual_duration() {
+                    Some(duration) => {
+                        info!(
+                            "Manual speed override: {}% for {:?}",
+                            manual.speed, duration
+                        )
+                    }
+                    None => info!("Manual speed override: {}%", manual.speed),
+                }
+            }
+            controller
+        }
+
+        fn from_cli_args(cli_args: &impl CliArgsTrait) -> Self {
+            let mut controller = FanController {
+                curves: cli_args.get_curves(),
+                curve_policy: cli_args.get_curve_policy(),
+                profiles: cli_args.get_profiles(),
+                profile: None,
+                quiet_critical_temp: cli_args.get_quiet_critical_temp(),
+                throttle_boost: cli_args.get_throttle_boost(),
+                pwm_freq: cli_args.get_pwm_freq(),
+                manual_speed: cli_args
+                    .get_manual_speed()
+                    .map(|speed| ManualOverride::new(speed, cli_args.get_manual_duration())),
+                manual_override: None,
+            };
+            // checked against the known profiles when parsing the command line
+            if let Some(name) = cli_args.get_profile() {
+                controller.set_profile(name);
+            }
+            controller
+        }
+
+        /// A profile replaces the curve of the first sensor, the main one, keeping its sensor and
+        /// weight; the other sensors keep their curves. False for an unknown profile.
+        fn set_profile(&mut self, name: String) -> bool {
+            let Some(profile) = self.profiles.iter().find(|p| p.get_name() == name) else {
+                return false;
+            };
+            if let Some(first) = self.curves.first_mut() {
+                *first = SensorCurve::new(
+                    first.get_sensor().to_string(),
+                    profile.get_curve().clone(),
+                    first.get_weight(),
+                );
+            }
+            self.profile = Some(name);
+            true
+        }
+
+        pub fn get_curves(&self) -> &[SensorCurve] {
+            &self.curves
+        }
+
+        pub fn get_profile(&self) -> Option<&str> {
+            self.profile.as_deref()
+        }
+
+        /// Apply a command, `reload` goes back to the command line settings.
+        pub fn apply(&mut self, command: ControlCommand, cli_args: &impl CliArgsTrait) {
+            match command {
+                ControlCommand::SetManual(speed) => {
+                    info!("Manual speed override: {}%", speed);
+                    self.manual_override = Some(ManualOverride::new(speed, None));
+                }
+                ControlCommand::SetManualFor(speed, duration) => {
+                    info!("Manual speed override: {}% for {:?}", speed, duration);
+                    self.manual_override = Some(ManualOverride::new(speed, Some(duration)));
+                }
+                ControlCommand::ClearManual => {
+                    if self.manual_override.take().is_some() {
+                        info!("Manual speed override cleared, back to automatic control");
+                    }
+                }
+                ControlCommand::SetCurve(sensor_curve) => {
+                    match self
+                        .curves
+                        .iter()
+                        .position(|sc| sc.get_sensor() == sensor_curve.get_sensor())
+                    {
+                        Some(index) => {
+                            info!("Curve replaced: {}", sensor_curve);
+                            self.curves[index] = sensor_curve;
+                            // the curve of the profile is gone
+                            if index == 0 {
+                                self.profile = None;
+                            }
+                        }
+                        None => warn!("No curve for sensor {}", sensor_curve.get_sensor()),
+                    }
+                }
+                ControlCommand::SetProfile(name) => {
+                    if self.set_profile(name.clone()) {
+                        info!("Curve profile {} applied", name);
+                    } else {
+                        warn!("Unknown curve profile {}", name);
+                    }
+                }
+                ControlCommand::Reload => {
+                    info!("Settings reloaded from the command line");
+                    // the command line override keeps its expiry, or stays dropped once expired
+                    *self = FanController {
+                        manual_speed: self.manual_speed,
+                        ..Self::from_cli_args(cli_args)
+                    };
+                }
+            }
+        }
+
+        /// Drop the manual overrides whose duration is over.
+        pub fn expire(&mut self, now: Instant) {
+            for manual in [&mut self.manual_override, &mut self.manual_speed] {
+                if let Some(expired) = manual.take_if(|m| m.expired(now)) {
+                    info!(
+                        "Manual speed override of {}% expired, back to automatic control",
+                        expired.speed
+                    );
+                }
+            }
+        }
+
+        /// Time left before the active manual override expires.
+        pub fn get_manual_remaining(&self, now: Instant) -> Option<Duration> {
+            self.manual_override
+                .or(self.manual_speed)
+                .and_then(|m| m.until)
+                .map(|until| until.saturating_duration_since(now))
+        }
+
+        /// Runtime settings at `now`, to resume them in the next run.
+        pub fn get_settings(
+            &self,
+            now: Instant,
+            cli_args: &impl CliArgsTrait,
+        ) -> ControllerSettings {
+            let remaining =
+                |m: ManualOverride| m.until.map(|until| until.saturating_duration_since(now));
+            ControllerSettings {
+                manual_speed_remaining: cli_args.get_manual_duration().map(|_| {
+                    self.manual_speed
+                        .and_then(remaining)
+                        .unwrap_or(Duration::ZERO)
+                }),
+                manual_override: self.manual_override.map(|m| (m.speed, remaining(m))),
+                profile: self.profile.clone(),
+            }
+        }
+
+        /// Resume the runtime settings of a previous run, the timed command line override
+        /// keeps its expiry instead of starting again.
+        pub fn set_settings(
+            &mut self,
+            settings: ControllerSettings,
+            now: Instant,
+            cli_args: &impl CliArgsTrait,
+        ) {
+            if let (Some(manual), Some(remaining)) =
+                (self.manual_speed.as_mut(), settings.manual_speed_remaining)
+            {
+                if manual.until.is_some() {
+                    manual.until = Some(now + remaining);
+                }
+            }
+            self.manual_override =
+                settings
+                    .manual_override
+                    .map(|(speed, remaining)| ManualOverride {
+                        speed,
+                        until: remaining.map(|r| now + r),
+                    });
+            if let Some(name) = settings.profile {
+                if self.profile.as_ref() != Some(&name) {
+                    self.apply(ControlCommand::SetProfile(name), cli_args);
+                }
+            }
+        }
+
+        /// One iteration of the control loop at `now`, shared by the daemon, oneshot runs,
+        /// replays, simulations and the standalone monitor: drop the expired overrides, compute
+        /// the target, raise it to the throttle boost while the firmware soft temperature limit
+        /// is active, go to full speed after a sensor error and apply the duty to `pwm_manager`.
+        pub fn step(
+            &mut self,
+            now: Instant,
+            input: ControlInput,
+            pwm_manager: &dyn PwmManagerTrait,
+        ) -> ControlStep {
+            self.expire(now);
+            let (target, mode) = self.target(input.temps, input.quiet);
+            let (duty, mode) = if input.sensor_error {
+                (1.0, FanMode::Failsafe)
+            } else {
+                match self.throttle_boost {
+                    Some(boost)
+                        if mode != FanMode::Manual
+                            && input.throttle_flags.is_some_and(|f| f.is_soft_temp_limit()) =>
+                    {
+                        (target.max(f64::from(boost) / 100.0), mode)
+                    }
+                    _ => (target, mode),
+                }
+            };
+            ControlStep {
+                target,
+                duty,
+                mode,
+                error: pwm_manager.set_frequency(self.pwm_freq, duty).err(),
+            }
+        }
+
+        /// Duty cycle in 0.0..=1.0 for the temperatures read in curves order.
+        /// A quiet profile is ignored above the critical temperature.
+        pub fn target(&self, temps: &[u8], quiet: Option<&QuietProfile>) -> (f64, FanMode) {
+            if let Some(manual) = self.manual_override.or(self.manual_speed) {
+                debug!("manual speed: {}", manual.speed);
+                return (f64::from(manual.speed) / 100.0, FanMode::Manual);
+            }
+
+            let critical = temps.iter().any(|&t| t >= self.quiet_critical_temp);
+            match quiet {
+                Some(profile) if !critical => {
+                    let readings: Vec<(&SensorCurve, u8)> = self
+                        .curves
+                        .iter()
+                        .zip(temps.iter().map(|&t| profile.shift(t)))
+                        .collect();
+                    (
+                        profile.cap(fan_curve::combine(self.curve_policy, &readings)),
+                        FanMode::Quiet,
+                    )
+                }
+                _ => {
+                    let readings: Vec<(&SensorCurve, u8)> =
+                        self.curves.iter().zip(temps.iter().copied()).collect();
+                    (
+                        fan_curve::combine(self.curve_policy, &readings),
+                        FanMode::Auto,
+                    )
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::cli_arguments::cli_args::{MockArgs, MockCliArgsTrait};
+        use crate::curve::fan_curve::FanCurve;
+        use crate::pwm::pwm_manager::NullPwmManager;
+
+        fn cli_mock(
+            manual_speed: Option<u8>,
+            manual_duration: Option<Duration>,
+        ) -> MockCliArgsTrait {
+            MockArgs {
+                curves: vec![
+                    SensorCurve::new(
+                        "cpu".to_string(),
+                        FanCurve::new(vec![50, 70], vec![20, 100]),
+                        1.0,
+                    ),
+                    SensorCurve::new(
+                        "nvme".to_string(),
+                        FanCurve::new(vec![40, 60], vec![0, 60]),
+                        1.0,
+                    ),
+                ],
+                manual_speed,
+                manual_duration,
+                throttle_boost: Some(80),
+                ..Default::default()
+            }
+            .mock()
+        }
+
+        #[test]
+        fn test_target_auto() {
+            let controller = FanController::new(&cli_mock(None, None));
+            assert_eq!(controller.target(&[60, 30], None), (0.6, FanMode::Auto));
+        }
+
+        #[test]
+        fn test_target_quiet() {
+            let controller = FanController::new(&cli_mock(None, None));
+            let quiet: QuietProfile = "22:00-07:00,40,5".parse().unwrap();
+            // 65°C read as 60°C, 60% capped at 40%
+            assert_eq!(
+                controller.target(&[65, 30], Some(&quiet)),
+                (0.4, FanMode::Quiet)
+            );
+            assert_eq!(
+                controller.target(&[52, 30], Some(&quiet)),
+                (0.2, FanMode::Quiet)
+            );
+            // no cap at the critical temperature
+            assert_eq!(
+                controller.target(&[75, 30], Some(&quiet)),
+                (1.0, FanMode::Auto)
+            );
+        }
+
+        #[test]
+        fn test_step() {
+            let cli_mock = cli_mock(None, None);
+            let mut controller = FanController::new(&cli_mock);
+            let pwm_manager = NullPwmManager::default();
+            let mut step = |temps: &[u8], sensor_error, throttle_flags| {
+                let input = ControlInput {
+                    temps,
+                    sensor_error,
+                    throttle_flags,
+                    ..Default::default()
+                };
+                let step = controller.step(Instant::now(), input, &pwm_manager);
+                (step.target, step.get_applied(), step.mode)
+            };
+
+            assert_eq!(
+                step(&[60, 30], false, None),
+                (0.6, Some(0.6), FanMode::Auto)
+            );
+            // boosted while the soft temperature limit is active
+            let soft_limit = Some(ThrottleFlags::new(0x8));
+            assert_eq!(
+                step(&[60, 30], false, soft_limit),
+                (0.6, Some(0.8), FanMode::Auto)
+            );
+            // full speed after a sensor error, the target keeps the last readings
+            assert_eq!(
+                step(&[60, 30], true, None),
+                (0.6, Some(1.0), FanMode::Failsafe)
+            );
+            assert_eq!(pwm_manager.get_duty(), 1.0);
+        }
+
+        #[test]
+        fn test_manual_override() {
+            let cli_mock = cli_mock(Some(40), None);
+            let mut controller = FanController::new(&cli_mock);
+            assert_eq!(controller.target(&[60, 30], None), (0.4, FanMode::Manual));
+
+            // the runtime override wins over the command line
+            controller.apply(ControlCommand::SetManual(90), &cli_mock);
+            assert_eq!(controller.target(&[60, 30], None), (0.9, FanMode::Manual));
+
+            controller.apply(ControlCommand::ClearManual, &cli_mock);
+            assert_eq!(controller.target(&[60, 30], None), (0.4, FanMode::Manual));
+        }
+
+        #[test]
+        fn test_timed_override() {
+            let cli_mock = cli_mock(Some(40), Some(Duration::from_secs(600)));
+            let mut controller = FanController::new(&cli_mock);
+            controller.apply(
+                ControlCommand::SetManualFor(100, Duration::from_secs(60)),
+                &cli_mock,
+            );
+            let now = Instant::now();
+
+            assert_eq!(controller.target(&[60, 30], None), (1.0, FanMode::Manual));
+            assert!(controller.get_manual_remaining(now).unwrap() <= Duration::from_secs(60));
+
+            // the runtime override ends first, then the command line one
+            controller.expire(now + Duration::from_secs(61));
+            assert_eq!(controller.target(&[60, 30], None), (0.4, FanMode::Manual));
+            controller.expire(now + Duration::from_secs(601));
+            assert_eq!(controller.target(&[60, 30], None), (0.6, FanMode::Auto));
+            assert_eq!(controller.get_manual_remaining(now), None);
+
+            // an expired command line override isn't started again by a reload
+            controller.apply(ControlCommand::Reload, &cli_mock);
+            assert_eq!(controller.target(&[60, 30], None), (0.6, FanMode::Auto));
+        }
+
+        #[test]
+        fn test_settings_across_runs() {
+            let cli_mock = cli_mock(Some(40), Some(Duration::from_secs(600)));
+            let mut first = FanController::new(&cli_mock);
+            first.apply(
+                ControlCommand::SetManualFor(90, Duration::from_secs(60)),
+                &cli_mock,
+            );
+            first.apply(
+                ControlCommand::SetProfile("performance".to_string()),
+                &cli_mock,
+            );
+            let now = Instant::now();
+
+            let mut second = FanController::new(&cli_mock);
+            let later = now + Duration::from_secs(300);
+            second.set_settings(first.get_settings(later, &cli_mock), later, &cli_mock);
+            assert_eq!(second.get_profile(), Some("performance"));
+            second.expire(later);
+            assert_eq!(second.target(&[60, 30], None), (0.4, FanMode::Manual));
+            assert!(second.get_manual_remaining(later).unwrap() <= Duration::from_secs(300));
+
+            // an expired command line override stays dropped in the next run
+            let end = now + Duration::from_secs(601);
+            second.expire(end);
+            let mut third = FanController::new(&cli_mock);
+            third.set_settings(second.get_settings(end, &cli_mock), end, &cli_mock);
+            third.expire(end);
+            assert_eq!(third.target(&[55, 30], None), (0.7, FanMode::Auto));
+        }
+
+        #[test]
+        fn test_set_curve_and_reload() {
+            let cli_mock = cli_mock(None, None);
+            let mut controller = FanController::new(&cli_mock);
+
+            let curve: SensorCurve = "nvme:40,60:50,100".parse().unwrap();
+            controller.apply(ControlCommand::SetCurve(curve.clone()), &cli_mock);
+            assert_eq!(controller.get_curves()[1], curve);
+            assert_eq!(controller.target(&[60, 50], None), (0.75, FanMode::Auto));
+
+            // unknown sensors are ignored
+            let unknown: SensorCurve = "gpu:40,60:50,100".parse().unwrap();
+            controller.apply(ControlCommand::SetCurve(unknown), &cli_mock);
+            assert_eq!(controller.get_curves().len(), 2);
+
+            controller.apply(
+                ControlCommand::SetProfile("performance".to_string()),
+                &cli_mock,
+            );
+            assert_eq!(controller.get_profile(), Some("performance"));
+            assert_eq!(controller.target(&[55, 30], None), (0.7, FanMode::Auto));
+            controller.apply(ControlCommand::SetProfile("turbo".to_string()), &cli_mock);
+            assert_eq!(controller.get_profile(), Some("performance"));
+
+            // the profile only sets the curve of the first sensor
+            assert_eq!(controller.get_curves()[1], curve);
+            controller.apply(ControlCommand::SetCurve(curve), &cli_mock);
+            assert_eq!(controller.get_profile(), Some("performance"));
+            let cpu: SensorCurve = "cpu:50,70:20,100".parse().unwrap();
+            controller.apply(ControlCommand::SetCurve(cpu), &cli_mock);
+            assert_eq!(controller.get_profile(), None);
+
+            controller.apply(ControlCommand::SetManual(30), &cli_mock);
+            controller.apply(ControlCommand::Reload, &cli_mock);
+            assert_eq!(controller, FanController::new(&cli_mock));
+        }
+    }
+}
@@ -0,0 +1,520 @@
+pub mod fan_curve {
+    use clap::ValueEnum;
+    use log::debug;
+    use std::fmt;
+    use std::path::Path;
+    use std::str::FromStr;
+
+    /// Piecewise linear curve mapping a temperature in Celsius to a fan speed in percentage.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct FanCurve {
+        temp_step: Vec<u8>,
+        speed_step: Vec<u8>,
+    }
+
+    impl FanCurve {
+        pub fn new(temp_step: Vec<u8>, speed_step: Vec<u8>) -> Self {
+            FanCurve {
+                temp_step,
+                speed_step,
+            }
+        }
+
+        /// As many speed steps as temperature steps, temperatures strictly increasing:
+        /// the interpolation divides by the difference between two steps.
+        pub fn valid(&self) -> bool {
+            !self.temp_step.is_empty()
+                && self.temp_step.len() == self.speed_step.len()
+                && self.temp_step.windows(2).all(|w| w[0] < w[1])
+        }
+
+        pub fn get_temp_step(&self) -> &[u8] {
+            &self.temp_step
+        }
+
+        pub fn get_speed_step(&self) -> &[u8] {
+            &self.speed_step
+        }
+
+        /// The curve is active once the temperature reaches its first step.
+        pub fn is_active(&self, temp: u8) -> bool {
+            temp >= self.temp_step[0]
+        }
+
+        /// Distance in Celsius between the temperature and the closest step.
+        pub fn distance_to_step(&self, temp: u8) -> u8 {
+            self.temp_step
+                .iter()
+                .map(|&step| step.abs_diff(temp))
+                .min()
+                .unwrap_or(u8::MAX)
+        }
+
+        // Get speed interpolating array's values
+        pub fn speed_at(&self, temp: u8) -> u8 {
+            let cfg_speed = &self.speed_step;
+            let cfg_temp = &self.temp_step;
+
+            let mut speed: u8 = *cfg_speed.last().unwrap();
+            let last_temp = *cfg_temp.last().unwrap();
+
+            // temp below first value
+            if temp < cfg_temp[0] {
+                debug!("min speed: {}", cfg_speed[0]);
+                speed = cfg_speed[0];
+            } else if temp >= last_temp {
+                debug!("max speed: {}", speed);
+                // max value already selected
+            } else {
+                for (i, step) in cfg_temp.windows(2).enumerate() {
+                    let (step_temp, next_step_temp) = (step[0], step[1]);
+
+                    debug!("Temperature step[{}]: {}", i, step_temp);
+                    debug!("Temperature next step[{}]: {}", i + 1, next_step_temp);
+
+                    if (temp >= step_temp) && (temp <= next_step_temp) {
+                        // Linear interpolation, signed to allow decreasing segments
+                        let temp_range = i32::from(next_step_temp) - i32::from(step_temp);
+                        let speed_range = i32::from(cfg_speed[i + 1]) - i32::from(cfg_speed[i]);
+                        let temp_diff = i32::from(temp) - i32::from(step_temp);
+
+                        debug!("temp_diff: {}", temp_diff);
+                        debug!("temp_range: {}", temp_range);
+                        debug!("speed_range: {}", speed_range);
+
+                        let calc = speed_range * temp_diff / temp_range;
+
+                        speed = (i32::from(cfg_speed[i]) + calc).clamp(0, 100) as u8;
+                        debug!("Linear interpolation: {}", speed);
+                        break;
+                    }
+                }
+            }
+
+            debug!("temp: {}", temp);
+            debug!("speed: {}", speed);
+            speed
+        }
+    }
+
+    // Format: TEMP_STEPS:SPEED_STEPS, e.g. 50,70,80:20,50,100
+    impl FromStr for FanCurve {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let (temps, speeds) = s
+                .split_once(':')
+                .ok_or_else(|| format!("`{s}` isn't in the TEMP_STEPS:SPEED_STEPS format"))?;
+
+            let parse_steps = |v: &str| -> Result<Vec<u8>, String> {
+                v.split(',')
+                    .map(|x| {
+                        x.trim()
+                            .parse::<u8>()
+                            .map_err(|_| format!("`{x}` isn't a valid step value"))
+                    })
+                    .collect()
+            };
+
+            let temp_step = parse_steps(temps)?;
+            let speed_step = parse_steps(speeds)?;
+            if speed_step.iter().any(|&v| v > 100) {
+                return Err("Speed step not in percentage range 0-100".to_string());
+            }
+
+            if temp_step.len() != speed_step.len() {
+                return Err(
+                    "The number of temperature steps must match the number of speed steps"
+                        .to_string(),
+                );
+            }
+            let curve = FanCurve::new(temp_step, speed_step);
+            if !curve.valid() {
+                return Err(format!("Temperature steps must increase in `{temps}`"));
+            }
+            Ok(curve)
+        }
+    }
+
+    /// A curve bound to the sensor file providing its temperature.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct SensorCurve {
+        sensor: String,
+        curve: FanCurve,
+        weight: f64,
+    }
+
+    impl SensorCurve {
+        pub fn new(sensor: String, curve: FanCurve, weight: f64) -> Self {
+            SensorCurve {
+                sensor,
+                curve,
+                weight,
+            }
+        }
+
+        pub fn get_sensor(&self) -> &str {
+            &self.sensor
+        }
+
+        pub fn get_curve(&self) -> &FanCurve {
+            &self.curve
+        }
+
+        pub fn get_weight(&self) -> f64 {
+            self.weight
+        }
+    }
+
+    impl fmt::Display for SensorCurve {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let join = |v: &[u8]| {
+                v.iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            };
+            write!(
+                f,
+                "{}:{}:{}:{}",
+                self.sensor,
+                join(self.curve.get_temp_step()),
+                join(self.curve.get_speed_step()),
+                self.weight
+            )
+        }
+    }
+
+    // Format: SENSOR:TEMP_STEPS:SPEED_STEPS[:WEIGHT], e.g. /sys/class/thermal/thermal_zone0/temp:50,70,80:20,50,100
+    // Sysfs paths may contain colons, e.g. soc:firmware or 0000:01:00.0, the fields are taken
+    // from the right: a trailing number after two step lists is the weight.
+    impl FromStr for SensorCurve {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let is_steps = |f: &str| {
+                !f.trim().is_empty()
+                    && f.chars()
+                        .all(|c| c.is_ascii_digit() || c == ',' || c == ' ')
+            };
+            let fields: Vec<&str> = s.rsplitn(4, ':').collect();
+            let (sensor, temps, speeds, weight) = match fields[..] {
+                [weight, speeds, temps, sensor]
+                    if is_steps(temps) && is_steps(speeds) && weight.parse::<f64>().is_ok() =>
+                {
+                    (sensor, temps, speeds, Some(weight))
+                }
+                _ => match s.rsplitn(3, ':').collect::<Vec<_>>()[..] {
+                    [speeds, temps, sensor] => (sensor, temps, speeds, None),
+                    _ => {
+                        return Err(format!(
+                            "`{s}` isn't in the SENSOR:TEMP_STEPS:SPEED_STEPS[:WEIGHT] format"
+                        ))
+                    }
+                },
+            };
+
+            let curve: FanCurve = format!("{temps}:{speeds}").parse()?;
+
+            let weight = match weight {
+                Some(w) => w
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|w| w.is_finite() && *w >= 0.0)
+                    .ok_or_else(|| format!("`{w}` isn't a valid weight"))?,
+                None => 1.0,
+            };
+
+            Ok(SensorCurve::new(sensor.to_string(), curve, weight))
+        }
+    }
+
+    /// Named curve selectable from the command line or the control socket.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct CurveProfile {
+        name: String,
+        curve: FanCurve,
+    }
+
+    impl CurveProfile {
+        pub fn new(name: &str, curve: FanCurve) -> Self {
+            CurveProfile {
+                name: name.to_string(),
+                curve,
+            }
+        }
+
+        pub fn get_name(&self) -> &str {
+            &self.name
+        }
+
+        pub fn get_curve(&self) -> &FanCurve {
+            &self.curve
+        }
+
+        /// Profiles available without any definition.
+        pub fn builtin() -> Vec<CurveProfile> {
+            vec![
+                CurveProfile::new("silent", FanCurve::new(vec![55, 70, 80], vec![0, 40, 100])),
+                CurveProfile::new(
+                    "balanced",
+                    FanCurve::new(vec![50, 70, 80], vec![20, 50, 100]),
+                ),
+                CurveProfile::new(
+                    "performance",
+                    FanCurve::new(vec![40, 55, 70], vec![40, 70, 100]),
+                ),
+            ]
+        }
+    }
+
+    // Format: NAME=TEMP_STEPS:SPEED_STEPS, e.g. night=60,75:0,100
+    impl FromStr for CurveProfile {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let (name, curve) = s
+                .split_once('=')
+                .ok_or_else(|| format!("`{s}` isn't in the NAME=TEMP_STEPS:SPEED_STEPS format"))?;
+            if name.trim().is_empty() {
+                return Err(format!("`{s}` has an empty profile name"));
+            }
+            Ok(CurveProfile::new(name.trim(), curve.parse()?))
+        }
+    }
+
+    /// How the speeds of several curves driving the same fan are combined.
+    #[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum CurvePolicy {
+        /// Highest speed requested by any curve
+        #[default]
+        Max,
+        /// Sum of the speeds scaled by each curve weight, capped at 100%
+        Sum,
+        /// First curve, in configuration order, that is above its first step
+        Priority,
+    }
+
+    /// Short name for displays: the zone directory of the thermal sysfs files, the path otherwise.
+    pub fn sensor_name(sensor: &str) -> String {
+        let path = Path::new(sensor);
+        match (path.file_name(), path.parent().and_then(Path::file_name)) {
+            (Some(file), Some(dir)) if file == "temp" => dir.to_string_lossy().into_owned(),
+            _ => sensor.to_string(),
+        }
+    }
+
+    /// Combine the speed of each (curve, temperature) pair, returning the duty cycle in 0.0..=1.0 .
+    pub fn combine(policy: CurvePolicy, readings: &[(&SensorCurve, u8)]) -> f64 {
+        let speeds: Vec<f64> = readings
+            .iter()
+            .map(|(sc, temp)| {
+                let speed = sc.curve.speed_at(*temp);
+                debug!("sensor {}: temp {} -> speed {}", sc.sensor, temp, speed);
+                f64::from(speed)
+            })
+            .collect();
+
+        let speed = match policy {
+            CurvePolicy::Max => speeds.iter().copied().fold(0.0, f64::max),
+            CurvePolicy::Sum => readings
+                .iter()
+                .zip(&speeds)
+                .map(|((sc, _), speed)| speed * sc.get_weight())
+                .sum::<f64>()
+                .min(100.0),
+            CurvePolicy::Priority => readings
+                .iter()
+                .zip(&speeds)
+                .find(|((sc, temp), _)| sc.curve.is_active(*temp))
+                .or_else(|| readings.iter().zip(&speeds).next())
+                .map_or(0.0, |(_, speed)| *speed),
+        };
+
+        debug!("policy {:?}: speed {}", policy, speed);
+        speed / 100.0
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn sensor_curve(temp_step: Vec<u8>, speed_step: Vec<u8>, weight: f64) -> SensorCurve {
+            SensorCurve::new(
+                "/dev/null".to_string(),
+                FanCurve::new(temp_step, speed_step),
+                weight,
+            )
+        }
+
+        #[test]
+        fn test_speed_at_outside_steps() {
+            let curve = FanCurve::new(vec![50, 70, 80], vec![20, 50, 100]);
+            assert_eq!(curve.speed_at(0), 20);
+            assert_eq!(curve.speed_at(40), 20);
+            assert_eq!(curve.speed_at(90), 100);
+            assert_eq!(curve.speed_at(255), 100);
+        }
+
+        #[test]
+        fn test_speed_at_interpolation() {
+            let curve = FanCurve::new(vec![50, 70, 80], vec![20, 50, 100]);
+            assert_eq!(curve.speed_at(50), 20);
+            assert_eq!(curve.speed_at(65), 42);
+            assert_eq!(curve.speed_at(70), 50);
+            assert_eq!(curve.speed_at(75), 75);
+            assert_eq!(curve.speed_at(80), 100);
+
+            let curve = FanCurve::new(vec![40, 60, 90], vec![10, 60, 80]);
+            assert_eq!(curve.speed_at(50), 35);
+            assert_eq!(curve.speed_at(75), 70);
+        }
+
+        #[test]
+        fn test_speed_at_single_step() {
+            let curve = FanCurve::new(vec![60], vec![80]);
+            assert_eq!(curve.speed_at(50), 80);
+            assert_eq!(curve.speed_at(60), 80);
+            assert_eq!(curve.speed_at(70), 80);
+        }
+
+        #[test]
+        fn test_speed_at_decreasing_segment() {
+            let curve = FanCurve::new(vec![40, 60], vec![60, 20]);
+            assert_eq!(curve.speed_at(50), 40);
+        }
+
+        #[test]
+        fn test_distance_to_step() {
+            let curve = FanCurve::new(vec![50, 70, 80], vec![20, 50, 100]);
+            assert_eq!(curve.distance_to_step(30), 20);
+            assert_eq!(curve.distance_to_step(68), 2);
+            assert_eq!(curve.distance_to_step(70), 0);
+            assert_eq!(curve.distance_to_step(90), 10);
+        }
+
+        #[test]
+        fn test_sensor_name() {
+            assert_eq!(
+                sensor_name("/sys/class/thermal/thermal_zone0/temp"),
+                "thermal_zone0"
+            );
+            assert_eq!(
+                sensor_name("/sys/class/hwmon/hwmon1/temp1_input"),
+                "/sys/class/hwmon/hwmon1/temp1_input"
+            );
+        }
+
+        #[test]
+        fn test_combine_max() {
+            let cpu = sensor_curve(vec![50, 70, 80], vec![20, 50, 100], 1.0);
+            let nvme = sensor_curve(vec![40, 60], vec![30, 90], 1.0);
+
+            assert_eq!(combine(CurvePolicy::Max, &[(&cpu, 75), (&nvme, 40)]), 0.75);
+            assert_eq!(combine(CurvePolicy::Max, &[(&cpu, 50), (&nvme, 60)]), 0.9);
+        }
+
+        #[test]
+        fn test_combine_sum() {
+            let cpu = sensor_curve(vec![50, 70, 80], vec![20, 50, 100], 0.5);
+            let nvme = sensor_curve(vec![40, 60], vec![30, 90], 0.5);
+
+            assert_eq!(combine(CurvePolicy::Sum, &[(&cpu, 50), (&nvme, 40)]), 0.25);
+            // capped at full speed
+            let nvme = sensor_curve(vec![40, 60], vec![30, 90], 1.0);
+            assert_eq!(combine(CurvePolicy::Sum, &[(&cpu, 80), (&nvme, 60)]), 1.0);
+        }
+
+        #[test]
+        fn test_combine_priority() {
+            let nvme = sensor_curve(vec![60, 70], vec![40, 100], 1.0);
+            let cpu = sensor_curve(vec![50, 70, 80], vec![20, 50, 100], 1.0);
+
+            // nvme below its first step, cpu is the first active curve
+            assert_eq!(
+                combine(CurvePolicy::Priority, &[(&nvme, 50), (&cpu, 75)]),
+                0.75
+            );
+            // nvme active, wins even if cpu requests more
+            assert_eq!(
+                combine(CurvePolicy::Priority, &[(&nvme, 60), (&cpu, 80)]),
+                0.4
+            );
+            // nothing active, first curve speed
+            assert_eq!(
+                combine(CurvePolicy::Priority, &[(&nvme, 30), (&cpu, 30)]),
+                0.4
+            );
+        }
+
+        #[test]
+        fn test_parse_sensor_curve() {
+            let sc: SensorCurve = "/sys/class/nvme/temp:40,60:30,90:0.5".parse().unwrap();
+            assert_eq!(sc.get_sensor(), "/sys/class/nvme/temp");
+            assert_eq!(sc.get_curve().get_temp_step(), &[40, 60]);
+            assert_eq!(sc.get_curve().get_speed_step(), &[30, 90]);
+            assert_eq!(sc.get_weight(), 0.5);
+
+            let sc: SensorCurve = "/tmp/temp:50:100".parse().unwrap();
+            assert_eq!(sc.get_weight(), 1.0);
+        }
+
+        #[test]
+        fn test_parse_sensor_curve_colon_in_path() {
+            let sensor = "/sys/devices/platform/soc/soc:firmware/temp";
+            let sc: SensorCurve = format!("{sensor}:50,70:20,100").parse().unwrap();
+            assert_eq!(sc.get_sensor(), sensor);
+            assert_eq!(sc.get_curve().get_temp_step(), &[50, 70]);
+            assert_eq!(sc.get_weight(), 1.0);
+
+            let sensor = "/sys/bus/pci/devices/0000:01:00.0/hwmon/hwmon1/temp1_input";
+            let sc: SensorCurve = format!("{sensor}:40,60:30,90:0.5").parse().unwrap();
+            assert_eq!(sc.get_sensor(), sensor);
+            assert_eq!(sc.get_curve().get_speed_step(), &[30, 90]);
+            assert_eq!(sc.get_weight(), 0.5);
+
+            // Display round trip, as in the status of the control socket
+            assert_eq!(sc.to_string().parse::<SensorCurve>(), Ok(sc));
+        }
+
+        #[test]
+        fn test_parse_profile() {
+            let profile: CurveProfile = "night=60,75:0,100".parse().unwrap();
+            assert_eq!(profile.get_name(), "night");
+            assert_eq!(
+                profile.get_curve(),
+                &FanCurve::new(vec![60, 75], vec![0, 100])
+            );
+
+            assert!("60,75:0,100".parse::<CurveProfile>().is_err());
+            assert!("=60,75:0,100".parse::<CurveProfile>().is_err());
+            assert!("night=60,75:0".parse::<CurveProfile>().is_err());
+            assert!(CurveProfile::builtin()
+                .iter()
+                .all(|p| p.get_curve().valid()));
+        }
+
+        #[test]
+        fn test_parse_sensor_curve_invalid() {
+            assert!("/tmp/temp:50,70".parse::<SensorCurve>().is_err());
+            assert!("/tmp/temp:50,70:20".parse::<SensorCurve>().is_err());
+            assert!("/tmp/temp:50:120".parse::<SensorCurve>().is_err());
+            assert!("/tmp/temp:50:20:-1".parse::<SensorCurve>().is_err());
+        }
+
+        #[test]
+        fn test_equal_temperature_steps() {
+            assert!(!FanCurve::new(vec![50, 50, 80], vec![20, 60, 100]).valid());
+            assert!(!FanCurve::new(vec![70, 50], vec![20, 60]).valid());
+            assert!(FanCurve::new(vec![50, 60, 80], vec![20, 60, 100]).valid());
+            assert_eq!(
+                "50,50,80:20,60,100".parse::<FanCurve>(),
+                Err("Temperature steps must increase in `50,50,80`".to_string())
+            );
+            assert!("/tmp/temp:50,50,80:20,60,100"
+                .parse::<SensorCurve>()
+                .is_err());
+            assert!("night=60,60:0,100".parse::<CurveProfile>().is_err());
+        }
+    }
+}
@@ -0,0 +1,397 @@
+pub mod emergency_action {
+    use log::{error, info, warn};
+    use std::fmt;
+    use std::fs;
+    use std::io::{self};
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use std::str::FromStr;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const CPUFREQ_DIR: &str = "/sys/devices/system/cpu/cpufreq";
+
+    /// Action run when the temperature stays above the critical threshold.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum EmergencyAction {
+        /// Shell command, the temperature is in the RPI_FAN_TEMP environment variable
+        Hook(String),
+        /// `systemctl poweroff`
+        Poweroff,
+        /// Lower scaling_max_freq of every cpufreq policy, in kHz
+        CpuFreq(u32),
+    }
+
+    impl fmt::Display for EmergencyAction {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                EmergencyAction::Hook(command) => write!(f, "hook:{command}"),
+                EmergencyAction::Poweroff => write!(f, "poweroff"),
+                EmergencyAction::CpuFreq(khz) => write!(f, "cpufreq:{khz}"),
+            }
+        }
+    }
+
+    // Format: hook:COMMAND, poweroff or cpufreq:KHZ
+    impl FromStr for EmergencyAction {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.split_once(':') {
+                Some(("hook", command)) if !command.trim().is_empty() => {
+                    Ok(EmergencyAction::Hook(command.to_string()))
+                }
+                Some(("cpufreq", khz)) => khz
+                    .parse()
+                    .ok()
+                    .filter(|khz| *khz > 0)
+                    .map(EmergencyAction::CpuFreq)
+                    .ok_or_else(|| format!("`{khz}` isn't a frequency in kHz")),
+                None if s == "poweroff" => Ok(EmergencyAction::Poweroff),
+                _ => Err(format!(
+                    "`{s}` isn't one of hook:COMMAND, poweroff, cpufreq:KHZ"
+                )),
+            }
+        }
+    }
+
+    /// Runs the emergency actions, a dry-run implementation only logs them.
+    pub trait ActionExecutor {
+        fn run_hook(&mut self, command: &str, temp: u8) -> Result<(), io::Error>;
+
+        fn poweroff(&mut self) -> Result<(), io::Error>;
+
+        /// Limit the CPU frequency in kHz, `None` restores the hardware maximum.
+        fn set_max_freq(&mut self, khz: Option<u32>) -> Result<(), io::Error>;
+    }
+
+    #[derive(Debug, Default)]
+    pub struct SystemExecutor;
+
+    impl ActionExecutor for SystemExecutor {
+        fn run_hook(&mut self, command: &str, temp: u8) -> Result<(), io::Error> {
+            let mut child = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .env("RPI_FAN_TEMP", temp.to_string())
+                .spawn()?;
+            // the control loop never waits for the hook
+            thread::spawn(move || match child.wait() {
+                Ok(status) if !status.success() => warn!("Emergency hook failed: {}", status),
+                Ok(_) => {}
+                Err(e) => warn!("Emergency hook failed: {}", e),
+            });
+            Ok(())
+        }
+
+        fn poweroff(&mut self) -> Result<(), io::Error> {
+            let status = Command::new("systemctl").arg("poweroff").status()?;
+            if status.success() {
+                Ok(())
+            } else {
+                Err(io::Error::other(format!("systemctl poweroff: {status}")))
+            }
+        }
+
+        fn set_max_freq(&mut self, khz: Option<u32>) -> Result<(), io::Error> {
+            for policy in cpufreq_policies(Path::new(CPUFREQ_DIR))? {
+                let value = match khz {
+                    Some(khz) => khz.to_string(),
+                    None => fs::read_to_string(policy.join("cpuinfo_max_freq"))?
+                        .trim()
+                        .to_string(),
+                };
+                fs::write(policy.join("scaling_max_freq"), value)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn cpufreq_policies(dir: &Path) -> Result<Vec<PathBuf>, io::Error> {
+        let mut policies: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.join("scaling_max_freq").exists())
+            .collect();
+        policies.sort();
+        Ok(policies)
+    }
+
+    /// Logs the actions instead of running them.
+    #[derive(Debug, Default)]
+    pub struct DryRunExecutor {
+        executed: Vec<String>,
+    }
+
+    impl ActionExecutor for DryRunExecutor {
+        fn run_hook(&mut self, command: &str, temp: u8) -> Result<(), io::Error> {
+            info!("Dry run: hook `{}` at {}°C", command, temp);
+            self.executed.push(format!("hook:{command}"));
+            Ok(())
+        }
+
+        fn poweroff(&mut self) -> Result<(), io::Error> {
+            info!("Dry run: poweroff");
+            self.executed.push("poweroff".to_string());
+            Ok(())
+        }
+
+        fn set_max_freq(&mut self, khz: Option<u32>) -> Result<(), io::Error> {
+            info!("Dry run: CPU frequency limit {:?} kHz", khz);
+            self.executed.push(match khz {
+                Some(khz) => format!("cpufreq:{khz}"),
+                None => "cpufreq:restore".to_string(),
+            });
+            Ok(())
+        }
+    }
+
+    impl ActionExecutor for Box<dyn ActionExecutor> {
+        fn run_hook(&mut self, command: &str, temp: u8) -> Result<(), io::Error> {
+            (**self).run_hook(command, temp)
+        }
+
+        fn poweroff(&mut self) -> Result<(), io::Error> {
+            (**self).poweroff()
+        }
+
+        fn set_max_freq(&mut self, khz: Option<u32>) -> Result<(), io::Error> {
+            (**self).set_max_freq(khz)
+        }
+    }
+
+    /// Tracking of a `CriticalMonitor` kept across runs, times as ages.
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    pub struct CriticalState {
+        pub above_for: Option<Duration>,
+        pub fired_ago: Option<Duration>,
+        pub freq_limited: bool,
+    }
+
+    /// Fires the actions once the temperature stayed above `threshold` for `grace`,
+    /// at most once per `cooldown`. The CPU frequency is restored below the threshold
+    /// once the cooldown is over.
+    pub struct CriticalMonitor<E: ActionExecutor> {
+        threshold: u8,
+        grace: Duration,
+        cooldown: Duration,
+        actions: Vec<EmergencyAction>,
+        executor: E,
+        above_since: Option<Instant>,
+        last_fired: Option<Instant>,
+        freq_limited: bool,
+    }
+
+    impl<E: ActionExecutor> CriticalMonitor<E> {
+        pub fn new(
+            threshold: u8,
+            grace: Duration,
+            cooldown: Duration,
+            actions: Vec<EmergencyAction>,
+            executor: E,
+        ) -> Self {
+            CriticalMonitor {
+                threshold,
+                grace,
+                cooldown,
+                actions,
+                executor,
+                above_since: None,
+                last_fired: None,
+                freq_limited: false,
+            }
+        }
+
+        /// Tracking at `now`, to carry the grace period, cooldown and CPU frequency limit over
+        /// to the next run.
+        pub fn get_state(&self, now: Instant) -> CriticalState {
+            CriticalState {
+                above_for: self.above_since.map(|since| now.duration_since(since)),
+                fired_ago: self.last_fired.map(|fired| now.duration_since(fired)),
+                freq_limited: self.freq_limited,
+            }
+        }
+
+        /// Resume the tracking of a previous run, ages older than the monotonic clock are
+        /// dropped.
+        pub fn set_state(&mut self, state: CriticalState, now: Instant) {
+            self.above_since = state.above_for.and_then(|age| now.checked_sub(age));
+            self.last_fired = state.fired_ago.and_then(|age| now.checked_sub(age));
+            self.freq_limited = state.freq_limited;
+        }
+
+        /// Check the hottest temperature read at `now`.
+        pub fn update(&mut self, now: Instant, temp: u8) {
+            let cooled_down = self
+                .last_fired
+                .is_none_or(|fired| now.duration_since(fired) >= self.cooldown);
+
+            if temp < self.threshold {
+                if self.above_since.take().is_some() {
+                    info!("Temperature {}°C back below the critical threshold", temp);
+                }
+                if self.freq_limited && cooled_down {
+                    info!("Restoring the CPU frequency");
+                    match self.executor.set_max_freq(None) {
+                        Ok(_) => self.freq_limited = false,
+                        Err(e) => error!("Error restoring the CPU frequency: {}", e),
+                    }
+                }
+                return;
+            }
+
+            let above_since = *self.above_since.get_or_insert_with(|| {
+                warn!(temp_c = temp; "Critical temperature {}°C reached", temp);
+                now
+            });
+            if now.duration_since(above_since) < self.grace || !cooled_down {
+                return;
+            }
+
+            self.last_fired = Some(now);
+            for action in &self.actions {
+                warn!(temp_c = temp; "Critical temperature {}°C, running {}", temp, action);
+                let result = match action {
+                    EmergencyAction::Hook(command) => self.executor.run_hook(command, temp),
+                    EmergencyAction::Poweroff => self.executor.poweroff(),
+                    EmergencyAction::CpuFreq(khz) => {
+                        self.executor.set_max_freq(Some(*khz)).map(|_| {
+                            self.freq_limited = true;
+                        })
+                    }
+                };
+                if let Err(e) = result {
+                    error!("Error running emergency action {}: {}", action, e);
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn monitor(actions: Vec<EmergencyAction>) -> CriticalMonitor<DryRunExecutor> {
+            CriticalMonitor::new(
+                85,
+                Duration::from_secs(10),
+                Duration::from_secs(300),
+                actions,
+                DryRunExecutor::default(),
+            )
+        }
+
+        #[test]
+        fn test_parse() {
+            assert_eq!(
+                "hook:/usr/local/bin/alert --hot".parse(),
+                Ok(EmergencyAction::Hook(
+                    "/usr/local/bin/alert --hot".to_string()
+                ))
+            );
+            assert_eq!("poweroff".parse(), Ok(EmergencyAction::Poweroff));
+            assert_eq!(
+                "cpufreq:600000".parse(),
+                Ok(EmergencyAction::CpuFreq(600000))
+            );
+            assert!("cpufreq:fast".parse::<EmergencyAction>().is_err());
+            assert!("hook:".parse::<EmergencyAction>().is_err());
+            assert!("reboot".parse::<EmergencyAction>().is_err());
+        }
+
+        #[test]
+        fn test_grace_period() {
+            let mut monitor = monitor(vec![EmergencyAction::Poweroff]);
+            let start = Instant::now();
+
+            monitor.update(start, 86);
+            monitor.update(start + Duration::from_secs(9), 90);
+            assert!(monitor.executor.executed.is_empty());
+
+            // a dip below the threshold restarts the grace period
+            monitor.update(start + Duration::from_secs(10), 80);
+            monitor.update(start + Duration::from_secs(11), 86);
+            monitor.update(start + Duration::from_secs(20), 86);
+            assert!(monitor.executor.executed.is_empty());
+
+            monitor.update(start + Duration::from_secs(21), 86);
+            assert_eq!(monitor.executor.executed, vec!["poweroff"]);
+        }
+
+        #[test]
+        fn test_cooldown() {
+            let mut monitor = monitor(vec![EmergencyAction::Hook("alert".to_string())]);
+            let start = Instant::now();
+
+            monitor.update(start, 90);
+            monitor.update(start + Duration::from_secs(10), 90);
+            monitor.update(start + Duration::from_secs(60), 90);
+            // flapping around the threshold doesn't fire again
+            monitor.update(start + Duration::from_secs(61), 80);
+            monitor.update(start + Duration::from_secs(62), 90);
+            monitor.update(start + Duration::from_secs(100), 90);
+            assert_eq!(monitor.executor.executed, vec!["hook:alert"]);
+
+            monitor.update(start + Duration::from_secs(310), 90);
+            assert_eq!(monitor.executor.executed, vec!["hook:alert", "hook:alert"]);
+        }
+
+        #[test]
+        fn test_cpufreq_restore() {
+            let mut monitor = monitor(vec![EmergencyAction::CpuFreq(600000)]);
+            let start = Instant::now();
+
+            monitor.update(start, 90);
+            monitor.update(start + Duration::from_secs(10), 90);
+            // still in cooldown, the limit is kept
+            monitor.update(start + Duration::from_secs(60), 70);
+            assert_eq!(monitor.executor.executed, vec!["cpufreq:600000"]);
+
+            monitor.update(start + Duration::from_secs(310), 70);
+            monitor.update(start + Duration::from_secs(320), 70);
+            assert_eq!(
+                monitor.executor.executed,
+                vec!["cpufreq:600000", "cpufreq:restore"]
+            );
+        }
+
+        #[test]
+        fn test_state_across_runs() {
+            let actions = vec![EmergencyAction::CpuFreq(600000)];
+            let start = Instant::now() + Duration::from_secs(3600);
+
+            // one reading per run, as in oneshot mode
+            let mut first = monitor(actions.clone());
+            first.update(start, 90);
+            assert!(first.executor.executed.is_empty());
+
+            let mut second = monitor(actions.clone());
+            second.set_state(first.get_state(start), start);
+            second.update(start + Duration::from_secs(60), 90);
+            assert_eq!(second.executor.executed, vec!["cpufreq:600000"]);
+
+            // the limit is restored by a later run once the cooldown is over
+            let mut third = monitor(actions);
+            let now = start + Duration::from_secs(400);
+            third.set_state(second.get_state(now), now);
+            third.update(now, 70);
+            assert_eq!(third.executor.executed, vec!["cpufreq:restore"]);
+        }
+
+        #[test]
+        fn test_cpufreq_policies() {
+            let dir = std::env::temp_dir()
+                .join(format!("rpi-temp-fan-pwm-cpufreq-{}", std::process::id()));
+            for policy in ["policy0", "policy4"] {
+                fs::create_dir_all(dir.join(policy)).unwrap();
+                fs::write(dir.join(policy).join("scaling_max_freq"), "1800000").unwrap();
+            }
+            fs::create_dir_all(dir.join("boost")).unwrap();
+
+            assert_eq!(
+                cpufreq_policies(&dir).unwrap(),
+                vec![dir.join("policy0"), dir.join("policy4")]
+            );
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
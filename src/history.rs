@@ -0,0 +1,356 @@
+pub mod history_recorder {
+    use crate::controller::fan_controller::FanMode;
+    use chrono::{DateTime, FixedOffset, SecondsFormat};
+    use log::info;
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::path::{Path, PathBuf};
+
+    /// One update of the control loop, a CSV row of the history file.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct HistoryRow {
+        timestamp: DateTime<FixedOffset>,
+        temps: Vec<u8>,
+        target_duty: f64,
+        applied_duty: Option<f64>,
+        rpm: Option<u32>,
+        mode: FanMode,
+    }
+
+    impl HistoryRow {
+        pub fn new(
+            timestamp: DateTime<FixedOffset>,
+            temps: Vec<u8>,
+            target_duty: f64,
+            applied_duty: Option<f64>,
+            rpm: Option<u32>,
+            mode: FanMode,
+        ) -> Self {
+            HistoryRow {
+                timestamp,
+                temps,
+                target_duty,
+                applied_duty,
+                rpm,
+                mode,
+            }
+        }
+
+        pub fn get_timestamp(&self) -> DateTime<FixedOffset> {
+            self.timestamp
+        }
+
+        pub fn get_temps(&self) -> &[u8] {
+            &self.temps
+        }
+
+        pub fn get_target_duty(&self) -> f64 {
+            self.target_duty
+        }
+
+        pub fn get_applied_duty(&self) -> Option<f64> {
+            self.applied_duty
+        }
+
+        /// Parse a row written by `to_csv` with `sensors` temperature columns.
+        pub fn parse(line: &str, sensors: usize) -> Result<Self, String> {
+            let fields: Vec<&str> = line.trim_end().split(',').collect();
+            if fields.len() != sensors + 5 {
+                return Err(format!(
+                    "{} columns instead of {}",
+                    fields.len(),
+                    sensors + 5
+                ));
+            }
+            let optional = |i: usize| Some(fields[i]).filter(|s| !s.is_empty());
+            let number = |s: &str| {
+                s.parse::<f64>()
+                    .map_err(|_| format!("`{s}` isn't a duty cycle"))
+            };
+
+            Ok(HistoryRow::new(
+                DateTime::parse_from_rfc3339(fields[0])
+                    .map_err(|_| format!("`{}` isn't an RFC 3339 timestamp", fields[0]))?,
+                fields[1..=sensors]
+                    .iter()
+                    .map(|t| t.parse().map_err(|_| format!("`{t}` isn't a temperature")))
+                    .collect::<Result<_, _>>()?,
+                number(fields[sensors + 1])?,
+                optional(sensors + 2).map(number).transpose()?,
+                optional(sensors + 3)
+                    .map(|r| r.parse().map_err(|_| format!("`{r}` isn't an RPM")))
+                    .transpose()?,
+                fields[sensors + 4].parse()?,
+            ))
+        }
+
+        /// `timestamp,TEMP...,target_duty,applied_duty,rpm,mode`, missing values left empty.
+        pub fn to_csv(&self) -> String {
+            let mut fields = vec![self.timestamp.to_rfc3339_opts(SecondsFormat::Millis, false)];
+            fields.extend(self.temps.iter().map(u8::to_string));
+            fields.push(format!("{:.3}", self.target_duty));
+            fields.push(
+                self.applied_duty
+                    .map(|duty| format!("{duty:.3}"))
+                    .unwrap_or_default(),
+            );
+            fields.push(self.rpm.map(|rpm| rpm.to_string()).unwrap_or_default());
+            fields.push(self.mode.to_string());
+            fields.join(",")
+        }
+    }
+
+    /// Header naming one temperature column per sensor.
+    pub fn csv_header(sensors: &[String]) -> String {
+        let mut columns = vec!["timestamp".to_string()];
+        columns.extend(sensors.iter().cloned());
+        columns.extend(
+            ["target_duty", "applied_duty", "rpm", "mode"]
+                .iter()
+                .map(|c| c.to_string()),
+        );
+        columns.join(",")
+    }
+
+    /// Read a history file, giving the sensors of the header and the rows.
+    pub fn read(path: &Path) -> Result<(Vec<String>, Vec<HistoryRow>), io::Error> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut lines = BufReader::new(File::open(path)?).lines();
+
+        let header = lines
+            .next()
+            .transpose()?
+            .ok_or_else(|| invalid("empty history file".to_string()))?;
+        let columns: Vec<&str> = header.trim_end().split(',').collect();
+        if columns.len() < 6 || columns[0] != "timestamp" {
+            return Err(invalid(format!("`{header}` isn't a history header")));
+        }
+        let sensors: Vec<String> = columns[1..columns.len() - 4]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let mut rows = Vec::new();
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            rows.push(
+                HistoryRow::parse(&line, sensors.len())
+                    .map_err(|e| invalid(format!("line {}: {}", i + 2, e)))?,
+            );
+        }
+        Ok((sensors, rows))
+    }
+
+    /// Appends the rows to a CSV file rotated at `max_size` bytes to FILE.1 .. FILE.`count`.
+    pub struct HistoryRecorder {
+        path: PathBuf,
+        header: String,
+        max_size: u64,
+        count: u32,
+        file: Option<File>,
+        size: u64,
+    }
+
+    impl HistoryRecorder {
+        pub fn new(path: &Path, sensors: &[String], max_size: u64, count: u32) -> Self {
+            HistoryRecorder {
+                path: path.to_path_buf(),
+                header: csv_header(sensors),
+                max_size,
+                count,
+                file: None,
+                size: 0,
+            }
+        }
+
+        pub fn record(&mut self, row: &HistoryRow) -> Result<(), io::Error> {
+            let line = format!("{}\n", row.to_csv());
+            if self.file.is_none() {
+                self.open()?;
+            }
+            if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+                self.rotate()?;
+            }
+            self.write(&line)
+        }
+
+        // an existing file with other sensors is rotated away, its rows couldn't be read back
+        fn open(&mut self) -> Result<(), io::Error> {
+            if let Ok(file) = File::open(&self.path) {
+                let mut header = String::new();
+                BufReader::new(file).read_line(&mut header)?;
+                if !header.is_empty() && header.trim_end() != self.header {
+                    info!(
+                        "History file {} has different columns, rotating it",
+                        self.path.display()
+                    );
+                    self.rotate()?;
+                    return Ok(());
+                }
+            }
+            self.reopen()
+        }
+
+        fn reopen(&mut self) -> Result<(), io::Error> {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            self.size = file.metadata()?.len();
+            self.file = Some(file);
+            if self.size == 0 {
+                self.write(&format!("{}\n", self.header))?;
+            }
+            Ok(())
+        }
+
+        fn rotate(&mut self) -> Result<(), io::Error> {
+            self.file = None;
+            let rotated = |i: u32| PathBuf::from(format!("{}.{}", self.path.display(), i));
+            for i in (1..self.count).rev() {
+                match fs::rename(rotated(i), rotated(i + 1)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+            self.reopen()
+        }
+
+        fn write(&mut self, line: &str) -> Result<(), io::Error> {
+            match self.file.as_mut() {
+                Some(file) => {
+                    file.write_all(line.as_bytes())?;
+                    self.size += line.len() as u64;
+                    Ok(())
+                }
+                None => Err(io::Error::other("history file not open")),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn row(second: u32, temp: u8, applied_duty: Option<f64>) -> HistoryRow {
+            HistoryRow::new(
+                DateTime::parse_from_rfc3339(&format!("2026-06-01T12:00:{second:02}+02:00"))
+                    .unwrap(),
+                vec![temp, 41],
+                0.4,
+                applied_duty,
+                None,
+                FanMode::Auto,
+            )
+        }
+
+        fn dir(name: &str) -> PathBuf {
+            let dir = std::env::temp_dir().join(format!(
+                "rpi-temp-fan-pwm-history-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            dir
+        }
+
+        fn sensors() -> Vec<String> {
+            vec!["cpu".to_string(), "nvme".to_string()]
+        }
+
+        #[test]
+        fn test_to_csv() {
+            assert_eq!(
+                row(5, 52, Some(0.4)).to_csv(),
+                "2026-06-01T12:00:05.000+02:00,52,41,0.400,0.400,,auto"
+            );
+            assert_eq!(
+                row(5, 52, None).to_csv(),
+                "2026-06-01T12:00:05.000+02:00,52,41,0.400,,,auto"
+            );
+            assert_eq!(
+                csv_header(&sensors()),
+                "timestamp,cpu,nvme,target_duty,applied_duty,rpm,mode"
+            );
+        }
+
+        #[test]
+        fn test_parse() {
+            let row = HistoryRow::new(
+                DateTime::parse_from_rfc3339("2026-06-01T12:00:05.250+02:00").unwrap(),
+                vec![52, 41],
+                0.4,
+                None,
+                Some(2100),
+                FanMode::Quiet,
+            );
+            assert_eq!(HistoryRow::parse(&row.to_csv(), 2), Ok(row));
+            assert!(HistoryRow::parse("2026-06-01T12:00:05+02:00,52,0.4,,,auto", 2).is_err());
+            assert!(HistoryRow::parse("yesterday,52,41,0.4,,,auto", 2).is_err());
+            assert!(HistoryRow::parse("2026-06-01T12:00:05+02:00,52,41,0.4,,,loud", 2).is_err());
+        }
+
+        #[test]
+        fn test_record() {
+            let dir = dir("record");
+            let path = dir.join("history.csv");
+            let mut recorder = HistoryRecorder::new(&path, &sensors(), 1 << 20, 3);
+            recorder.record(&row(0, 50, Some(0.4))).unwrap();
+            // a restart appends to the same file
+            let mut recorder = HistoryRecorder::new(&path, &sensors(), 1 << 20, 3);
+            recorder.record(&row(1, 51, Some(0.4))).unwrap();
+
+            let contents = fs::read_to_string(&path).unwrap();
+            let lines: Vec<&str> = contents.lines().collect();
+            assert_eq!(lines.len(), 3);
+            assert_eq!(lines[0], csv_header(&sensors()));
+            assert!(lines[2].contains(",51,41,"));
+
+            let (read_sensors, rows) = read(&path).unwrap();
+            assert_eq!(read_sensors, sensors());
+            assert_eq!(rows, vec![row(0, 50, Some(0.4)), row(1, 51, Some(0.4))]);
+            let _ = fs::remove_dir_all(dir);
+        }
+
+        #[test]
+        fn test_rotation() {
+            let dir = dir("rotation");
+            let path = dir.join("history.csv");
+            // header and two rows per file
+            let max_size = (csv_header(&sensors()).len()
+                + 2 * (row(0, 50, None).to_csv().len() + 1)
+                + 1) as u64;
+            let mut recorder = HistoryRecorder::new(&path, &sensors(), max_size, 2);
+            for second in 0..7 {
+                recorder.record(&row(second, 50, None)).unwrap();
+            }
+
+            let rows = |p: &Path| fs::read_to_string(p).unwrap().lines().count() - 1;
+            assert_eq!(rows(&path), 1);
+            assert_eq!(rows(&dir.join("history.csv.1")), 2);
+            assert_eq!(rows(&dir.join("history.csv.2")), 2);
+            assert!(!dir.join("history.csv.3").exists());
+            let _ = fs::remove_dir_all(dir);
+        }
+
+        #[test]
+        fn test_other_sensors_rotated() {
+            let dir = dir("sensors");
+            let path = dir.join("history.csv");
+            fs::write(&path, "timestamp,cpu,target_duty,applied_duty,rpm,mode\n").unwrap();
+            let mut recorder = HistoryRecorder::new(&path, &sensors(), 1 << 20, 3);
+            recorder.record(&row(0, 50, None)).unwrap();
+
+            assert!(fs::read_to_string(&path)
+                .unwrap()
+                .starts_with(&csv_header(&sensors())));
+            assert!(dir.join("history.csv.1").exists());
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+pub mod journal_appender {
+    use log::kv::{Key, Value, VisitSource};
+    use log::{Level, Record};
+    use log4rs::append::Append;
+    use std::env;
+    use std::io::{self};
+    use std::os::unix::net::UnixDatagram;
+    use std::path::{Path, PathBuf};
+
+    /// Native protocol socket of systemd-journald.
+    pub const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+    const SYSLOG_IDENTIFIER: &str = "rpi-temp-fan-pwm";
+
+    /// Whether stderr is connected to the journal, as set by systemd for the service.
+    pub fn is_journal_stream() -> bool {
+        env::var_os("JOURNAL_STREAM").is_some()
+    }
+
+    /// Sends each record to journald as structured fields: MESSAGE, PRIORITY, the code
+    /// location and the record key-values uppercased, e.g. `temp_c` as TEMP_C.
+    #[derive(Debug)]
+    pub struct JournalAppender {
+        socket: UnixDatagram,
+        path: PathBuf,
+    }
+
+    impl JournalAppender {
+        pub fn new(path: &Path) -> Result<Self, io::Error> {
+            Ok(JournalAppender {
+                socket: UnixDatagram::unbound()?,
+                path: path.to_path_buf(),
+            })
+        }
+    }
+
+    impl Append for JournalAppender {
+        fn append(&self, record: &Record) -> anyhow::Result<()> {
+            self.socket.send_to(&encode(record), &self.path)?;
+            Ok(())
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// syslog(3) priority of a log level.
+    fn priority(level: Level) -> u8 {
+        match level {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        }
+    }
+
+    /// Journal field names are uppercase letters, digits and underscores,
+    /// not starting with an underscore reserved to journald.
+    fn field_name(key: &str) -> String {
+        let name: String = key
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+                _ => '_',
+            })
+            .collect();
+        name.trim_start_matches('_').to_string()
+    }
+
+    // KEY=VALUE, or the length-prefixed binary form for values with newlines
+    fn push_field(buf: &mut Vec<u8>, name: &str, value: &str) {
+        buf.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            buf.push(b'\n');
+            buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            buf.push(b'=');
+        }
+        buf.extend_from_slice(value.as_bytes());
+        buf.push(b'\n');
+    }
+
+    struct FieldVisitor<'a>(&'a mut Vec<u8>);
+
+    impl<'kvs> VisitSource<'kvs> for FieldVisitor<'_> {
+        fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+            let name = field_name(key.as_str());
+            if !name.is_empty() {
+                push_field(self.0, &name, &value.to_string());
+            }
+            Ok(())
+        }
+    }
+
+    fn encode(record: &Record) -> Vec<u8> {
+        let mut buf = Vec::new();
+        push_field(&mut buf, "MESSAGE", &record.args().to_string());
+        push_field(&mut buf, "PRIORITY", &priority(record.level()).to_string());
+        push_field(&mut buf, "SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER);
+        if let Some(module) = record.module_path() {
+            push_field(&mut buf, "CODE_MODULE", module);
+        }
+        if let Some(file) = record.file() {
+            push_field(&mut buf, "CODE_FILE", file);
+        }
+        if let Some(line) = record.line() {
+            push_field(&mut buf, "CODE_LINE", &line.to_string());
+        }
+        let _ = record.key_values().visit(&mut FieldVisitor(&mut buf));
+        buf
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::time::Duration;
+
+        #[test]
+        fn test_field_name() {
+            assert_eq!(field_name("temp_c"), "TEMP_C");
+            assert_eq!(field_name("fan.rpm"), "FAN_RPM");
+            assert_eq!(field_name("_pid"), "PID");
+        }
+
+        #[test]
+        fn test_encode() {
+            let kvs = [("sensor", "cpu"), ("temp_c", "52")];
+            let record = Record::builder()
+                .args(format_args!("Temperature read"))
+                .level(Level::Warn)
+                .module_path(Some("rpi_temp_fan_pwm"))
+                .key_values(&kvs)
+                .build();
+            let fields = String::from_utf8(encode(&record)).unwrap();
+            assert_eq!(
+                fields,
+                "MESSAGE=Temperature read\nPRIORITY=4\nSYSLOG_IDENTIFIER=rpi-temp-fan-pwm\n\
+                 CODE_MODULE=rpi_temp_fan_pwm\nSENSOR=cpu\nTEMP_C=52\n"
+            );
+        }
+
+        #[test]
+        fn test_multiline_message() {
+            let mut buf = Vec::new();
+            push_field(&mut buf, "MESSAGE", "a\nb");
+            let mut expected = b"MESSAGE\n".to_vec();
+            expected.extend_from_slice(&3u64.to_le_bytes());
+            expected.extend_from_slice(b"a\nb\n");
+            assert_eq!(buf, expected);
+        }
+
+        #[test]
+        fn test_append() {
+            let path = std::env::temp_dir()
+                .join(format!("rpi-temp-fan-pwm-journal-{}", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let journal = UnixDatagram::bind(&path).unwrap();
+            journal
+                .set_read_timeout(Some(Duration::from_secs(1)))
+                .unwrap();
+
+            let appender = JournalAppender::new(&path).unwrap();
+            let kvs = [("duty", 0.4)];
+            appender
+                .append(
+                    &Record::builder()
+                        .args(format_args!("Fan speed set"))
+                        .level(Level::Info)
+                        .key_values(&kvs)
+                        .build(),
+                )
+                .unwrap();
+
+            let mut buf = [0; 512];
+            let len = journal.recv(&mut buf).unwrap();
+            let fields = String::from_utf8_lossy(&buf[..len]);
+            assert!(fields.contains("PRIORITY=6\n"));
+            assert!(fields.contains("DUTY=0.4\n"));
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
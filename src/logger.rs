@@ -1,31 +1,178 @@
 pub mod app_logger {
 
-    use crate::cli_arguments::cli_args::CliArgsTrait;
+    use crate::cli_arguments::cli_args::{CliArgsTrait, LogArgs};
+    use crate::journal::journal_appender::{self, JournalAppender, JOURNAL_SOCKET};
+    use clap::ValueEnum;
+    use log::LevelFilter;
     use log4rs::append::console::ConsoleAppender;
-    use log4rs::config::{Appender, Root};
+    use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+    use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
+    use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
+    use log4rs::append::rolling_file::RollingFileAppender;
+    use log4rs::append::Append;
+    use log4rs::config::{Appender, Logger, Root};
+    use log4rs::encode::json::JsonEncoder;
     use log4rs::encode::pattern::PatternEncoder;
+    use log4rs::encode::Encode;
     use log4rs::Config;
+    use std::path::Path;
 
-    pub fn configure_logger(cli_args: &impl CliArgsTrait) {
+    const PATTERN: &str = "{d(%Y-%m-%d %H:%M:%S)(local)} - {l}: {m}{n}";
+
+    /// Format of the console and file logs, the journal always gets structured fields.
+    #[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+    pub enum LogFormat {
+        /// Timestamp, level and message, colored on the console
+        #[default]
+        Human,
+        /// One JSON object per line
+        Json,
+    }
+
+    fn encoder(format: LogFormat, highlight: bool) -> Box<dyn Encode> {
+        match format {
+            LogFormat::Human if highlight => {
+                Box::new(PatternEncoder::new(&format!("{{h({PATTERN})}}")))
+            }
+            LogFormat::Human => Box::new(PatternEncoder::new(PATTERN)),
+            LogFormat::Json => Box::new(JsonEncoder::new()),
+        }
+    }
+
+    pub fn configure_logger(
+        cli_args: &impl CliArgsTrait,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         // https://medium.com/nerd-for-tech/logging-in-rust-e529c241f92e
         // https://tms-dev-blog.com/log-to-a-file-in-rust-with-log4rs/
-        let stdout = ConsoleAppender::builder()
-            .encoder(Box::new(PatternEncoder::new(
-                "{h({d(%Y-%m-%d %H:%M:%S)(local)} - {l}: {m}{n})}",
-            )))
-            .build();
-        let config = Config::builder()
-            .appender(Appender::builder().build("stdout", Box::new(stdout)))
-            .build(
-                Root::builder().appender("stdout").build(
-                    cli_args
-                        .get_verbose()
-                        .log_level()
-                        .expect("Verbosity should be convertible to LevelFilter")
-                        .to_level_filter(),
-                ),
-            )
-            .unwrap();
-        let _handle = log4rs::init_config(config).unwrap();
+        let log = cli_args.get_log();
+        // under systemd the journal gets structured fields instead of colored lines
+        let journal = journal_appender::is_journal_stream()
+            .then(|| JournalAppender::new(Path::new(JOURNAL_SOCKET)).ok())
+            .flatten();
+        let stdout: Box<dyn Append> = match journal {
+            Some(journal) => Box::new(journal),
+            None => Box::new(
+                ConsoleAppender::builder()
+                    .encoder(encoder(log.get_format(), true))
+                    .build(),
+            ),
+        };
+
+        let mut config = Config::builder().appender(Appender::builder().build("stdout", stdout));
+        let mut root = Root::builder().appender("stdout");
+        if let Some(log_file) = log.get_file() {
+            config = config.appender(
+                Appender::builder().build("file", Box::new(rolling_file(&log, log_file)?)),
+            );
+            root = root.appender("file");
+        }
+        for (module, level) in log.get_levels() {
+            config = config.logger(Logger::builder().build(logger_name(module), *level));
+        }
+
+        let config = config.build(
+            root.build(
+                cli_args
+                    .get_verbose()
+                    .log_level()
+                    .expect("Verbosity should be convertible to LevelFilter")
+                    .to_level_filter(),
+            ),
+        )?;
+        let _handle = log4rs::init_config(config)?;
+        Ok(())
+    }
+
+    // log file rotated at the size limit to log_file.1 .. log_file.N, the oldest one deleted
+    fn rolling_file(
+        log: &LogArgs,
+        log_file: &Path,
+    ) -> Result<RollingFileAppender, Box<dyn std::error::Error>> {
+        let roller = FixedWindowRoller::builder().build(
+            &format!("{}.{{}}", log_file.display()),
+            log.get_file_count(),
+        )?;
+        let policy = CompoundPolicy::new(
+            Box::new(SizeTrigger::new(log.get_file_size())),
+            Box::new(roller),
+        );
+        Ok(RollingFileAppender::builder()
+            .encoder(encoder(log.get_format(), false))
+            .build(log_file, Box::new(policy))?)
+    }
+
+    /// Modules of this program are given without the crate name, e.g. `pwm`;
+    /// names with `::` are taken as full log targets, e.g. `rumqttc::state`.
+    fn logger_name(module: &str) -> String {
+        if module.contains("::") {
+            module.to_string()
+        } else {
+            format!("{}::{}", env!("CARGO_CRATE_NAME"), module)
+        }
+    }
+
+    /// Parse `MODULE=LEVEL`, e.g. `pwm=debug`.
+    pub fn parse_module_level(s: &str) -> Result<(String, LevelFilter), String> {
+        let (module, level) = s
+            .split_once('=')
+            .filter(|(module, _)| !module.trim().is_empty())
+            .ok_or_else(|| format!("`{s}` isn't in the MODULE=LEVEL format"))?;
+        let level = level
+            .trim()
+            .parse()
+            .map_err(|_| format!("`{level}` isn't one of off, error, warn, info, debug, trace"))?;
+        Ok((module.trim().to_string(), level))
+    }
+
+    /// Parse a size in bytes with an optional K, M or G binary suffix, e.g. `10M`.
+    pub fn parse_size(s: &str) -> Result<u64, String> {
+        let (number, multiplier) = match s.trim().to_ascii_uppercase() {
+            t if t.ends_with('K') => (t[..t.len() - 1].to_string(), 1 << 10),
+            t if t.ends_with('M') => (t[..t.len() - 1].to_string(), 1 << 20),
+            t if t.ends_with('G') => (t[..t.len() - 1].to_string(), 1 << 30),
+            t => (t, 1),
+        };
+        number
+            .parse::<u64>()
+            .ok()
+            .filter(|n| *n > 0)
+            .and_then(|n| n.checked_mul(multiplier))
+            .ok_or_else(|| format!("`{s}` isn't a size like 512K or 10M"))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_parse_module_level() {
+            assert_eq!(
+                parse_module_level("pwm=debug"),
+                Ok(("pwm".to_string(), LevelFilter::Debug))
+            );
+            assert_eq!(
+                parse_module_level("rumqttc::state=OFF"),
+                Ok(("rumqttc::state".to_string(), LevelFilter::Off))
+            );
+            assert!(parse_module_level("pwm").is_err());
+            assert!(parse_module_level("=debug").is_err());
+            assert!(parse_module_level("pwm=loud").is_err());
+        }
+
+        #[test]
+        fn test_parse_size() {
+            assert_eq!(parse_size("4096"), Ok(4096));
+            assert_eq!(parse_size("512K"), Ok(512 * 1024));
+            assert_eq!(parse_size("10m"), Ok(10 * 1024 * 1024));
+            assert!(parse_size("0").is_err());
+            assert!(parse_size("10MB").is_err());
+            assert!(parse_size("99999999999G").is_err());
+        }
+
+        #[test]
+        fn test_logger_name() {
+            assert_eq!(logger_name("pwm"), "rpi_temp_fan_pwm::pwm");
+            assert_eq!(logger_name("rumqttc::state"), "rumqttc::state");
+        }
     }
 }
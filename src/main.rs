@@ -1,63 +1,175 @@
 use std::io::{self};
 
 use cli_arguments::cli_args::CliArgsTrait;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 
-use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 //use num_traits::cast::ToPrimitive;
 
 use rppal::system::DeviceInfo;
 use std::env;
 use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 
 //use rppal::pwm::{Channel, Polarity, Pwm};
 
 use clap::Parser;
 
 mod cli_arguments;
-use crate::cli_arguments::cli_args::CliArgs;
+use crate::cli_arguments::cli_args::{CliArgs, Command};
+
+mod control;
+use crate::control::control_command::ControlCommand;
+
+mod controller;
+use crate::controller::fan_controller::{ControlInput, FanController, FanMode};
+
+mod curve;
+use crate::curve::fan_curve::{self, FanCurve, SensorCurve};
+
+mod emergency;
+use crate::emergency::emergency_action::{
+    ActionExecutor, CriticalMonitor, DryRunExecutor, SystemExecutor,
+};
+
+mod history;
+use crate::history::history_recorder::{self, HistoryRecorder, HistoryRow};
+
+mod journal;
 
 mod logger;
 use crate::logger::app_logger;
 
+mod metrics;
+use crate::metrics::fan_metrics::{Metrics, SharedMetrics};
+
+#[cfg(feature = "tui")]
+mod monitor;
+
+#[cfg(feature = "mqtt")]
+mod mqtt;
+
+mod notify;
+use crate::notify::sd_notify::Notifier;
+
+mod plot;
+use crate::plot::curve_plot;
+
 mod pwm;
-use crate::pwm::pwm_manager::PwmManager;
+use crate::pwm::parse_temp_string;
 use crate::pwm::pwm_manager::PwmManagerTrait;
+use crate::pwm::pwm_manager::{NullPwmManager, PwmManager};
+
+mod quiet;
+use crate::quiet::quiet_schedule::{LocalClock, QuietSchedule, SystemLocalClock};
+
+mod replay;
+use crate::replay::history_replay;
+
+mod scheduler;
+use crate::scheduler::adaptive_interval::AdaptiveInterval;
+use crate::scheduler::tick_scheduler::{MonotonicClock, TickScheduler};
+
+mod simulation;
+use crate::simulation::thermal_simulation::{self, LoadProfile, ThermalModel};
+
+mod socket;
+use crate::socket::control_socket::{self, ControlSocket, Request};
+
+mod state;
+use crate::state::controller_state::ControllerState;
+
+mod svg;
+use crate::svg::svg_plot;
+
+mod throttle;
+use crate::throttle::throttle_status::{
+    ThrottleMonitor, ThrottleReader, ThrottleSource, VCGENCMD_INTERVAL,
+};
+
+mod trip;
+use crate::trip::trip_points;
 
 // The simple-signal crate is used to handle incoming signals.
 use simple_signal::{self, Signal};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-
-const TEMP_FILE: &str = "/sys/class/thermal/thermal_zone0/temp";
+use std::sync::{mpsc, Arc, Mutex};
 
 // Gpio uses BCM pin numbering. BCM GPIO 23 is tied to physical pin 16.
 //const GPIO_LED: u8 = 23;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // parse CLI cli_args
-    let cli_args: CliArgs = CliArgs::parse();
+    let mut cli_args: CliArgs = CliArgs::parse();
 
-    app_logger::configure_logger(&cli_args);
+    app_logger::configure_logger(&cli_args)?;
+    cli_args.resolve_trip_point_curve();
 
     //println!("cli_args: {:#?} - {:#?}", cli_args.speed_step, cli_args.temp_step);
 
     if !cli_args.valid() {
-        error!("The number of temperature steps must match the number of speed steps");
+        error!("Temperature steps must increase and match the number of speed steps");
         return Err(io::Error::new(
             io::ErrorKind::InvalidInput,
-            "The number of temperature steps must match the number of speed steps",
+            "Temperature steps must increase and match the number of speed steps",
         )
         .into());
     }
 
+    if !cli_args.valid_intervals() {
+        error!("The minimum interval must not exceed the maximum interval");
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The minimum interval must not exceed the maximum interval",
+        )
+        .into());
+    }
+
+    if !cli_args.valid_profile() {
+        error!("Unknown curve profile");
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown curve profile").into());
+    }
+
+    match cli_args.get_command() {
+        Some(Command::Status { json }) => return print_status(&cli_args, json),
+        Some(Command::Replay { input, output }) => {
+            return replay_history(&cli_args, &input, output.as_deref())
+        }
+        Some(Command::ShowCurve { from, to }) => return show_curve(&cli_args, from, to),
+        Some(Command::Monitor {
+            standalone,
+            refresh,
+            manual_duration,
+        }) => return monitor(&cli_args, standalone, refresh, manual_duration),
+        Some(Command::ExportPlot {
+            output,
+            history,
+            from,
+            to,
+        }) => return export_plot(&cli_args, &output, history.as_deref(), from, to),
+        Some(Command::Simulate {
+            duration,
+            load,
+            ambient,
+            heat_capacity,
+            passive_conductance,
+            fan_conductance,
+            output,
+        }) => {
+            let model =
+                ThermalModel::new(ambient, heat_capacity, passive_conductance, fan_conductance);
+            return simulate_thermal(&cli_args, model, &load, duration, output.as_deref());
+        }
+        _ => {}
+    }
+
     _print_os_info();
 
-    if !in_container::in_container() {
-        let _ = {
-            // device_info unused, code to understand if it's raspberrry pi
+    if cli_args.is_dry_run() || !in_container::in_container() {
+        let device_info = {
+            // code to understand if it's raspberrry pi
             match DeviceInfo::new() {
                 Ok(device_info) => {
                     debug!(
@@ -65,13 +177,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         device_info.model(),
                         device_info.soc()
                     );
-                    device_info
+                    Some(device_info)
+                }
+                Err(e) if cli_args.is_dry_run() => {
+                    debug!("Error getting device info: {}", e);
+                    None
                 }
                 Err(e) => {
                     error!("Error getting device info: {}", e);
-                    return Err(
-                        io::Error::new(io::ErrorKind::Other, "Error getting device info").into(),
-                    );
+                    return Err(io::Error::other("Error getting device info").into());
                 }
             }
         };
@@ -85,41 +199,387 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         });
 
-        // create pwm struct
-        let pwm_manager = {
-            match PwmManager::build(cli_args.get_pwm_channel(), cli_args.get_pwm_freq(), 0.5) {
-                Ok(pwm_manager) => pwm_manager,
+        let state_file = cli_args.get_state_file();
+        let mut state = {
+            match ControllerState::load(&state_file, cli_args.get_state_max_age()) {
+                Ok(state) => state,
                 Err(e) => {
-                    error!("Error creating PWM manager: {}", e);
-                    return Err(e);
+                    warn!("Error loading state file {}: {}", state_file.display(), e);
+                    ControllerState::default()
+                }
+            }
+        };
+
+        // create pwm struct, starting from the last applied duty to avoid a speed jump
+        let pwm_duty = state.get_last_duty().unwrap_or(0.5);
+        let pwm_manager: Result<Box<dyn PwmManagerTrait>, _> = if cli_args.is_dry_run() {
+            info!("Dry run, the fan isn't driven");
+            NullPwmManager::build(
+                cli_args.get_pwm_channel(),
+                cli_args.get_pwm_freq(),
+                pwm_duty,
+            )
+            .map(|m| Box::new(m) as Box<dyn PwmManagerTrait>)
+        } else {
+            PwmManager::build(
+                cli_args.get_pwm_channel(),
+                cli_args.get_pwm_freq(),
+                pwm_duty,
+            )
+            .map(|m| Box::new(m) as Box<dyn PwmManagerTrait>)
+        };
+        let mut pwm_manager = match pwm_manager {
+            Ok(pwm_manager) => pwm_manager,
+            Err(e) => {
+                error!("Error creating PWM manager: {}", e);
+                return Err(e);
+            }
+        };
+
+        let mut notifier = Notifier::from_env();
+        notifier.ready();
+
+        let mut controller = FanController::new(&cli_args);
+        controller.set_settings(state.get_settings(), Instant::now(), &cli_args);
+        for sensor_curve in controller.get_curves() {
+            debug!("Curve: {}", sensor_curve);
+            check_trip_points(sensor_curve);
+        }
+        let mut quiet_schedule =
+            QuietSchedule::new(SystemLocalClock, cli_args.get_quiet_profiles());
+        let mut critical_monitor = build_critical_monitor(&cli_args);
+        if let Some(critical_monitor) = critical_monitor.as_mut() {
+            // each oneshot run reads once, the grace period and cooldown span the runs
+            critical_monitor.set_state(state.get_critical(), Instant::now());
+        }
+        let throttle_source =
+            ThrottleSource::detect(&cli_args.get_throttled_file(), device_info.is_some());
+        debug!("Throttling flags source: {:?}", throttle_source);
+        // the loop doesn't wait for vcgencmd, a oneshot run reads once
+        let throttle_reader = throttle_source.map(|source| {
+            if cli_args.is_oneshot() {
+                ThrottleReader::direct(source)
+            } else {
+                ThrottleReader::background(source, VCGENCMD_INTERVAL)
+            }
+        });
+        let mut throttle_monitor = ThrottleMonitor::default();
+
+        if cli_args.is_oneshot() {
+            // same pipeline as an iteration of the loop, without the remote interfaces
+            let temps = read_temps(controller.get_curves())?;
+            let throttle_flags = throttle_reader
+                .as_ref()
+                .and_then(|reader| throttle_monitor.read(reader));
+            if let Some(critical_monitor) = critical_monitor.as_mut() {
+                let hottest = temps.iter().copied().max().unwrap_or(0);
+                critical_monitor.update(Instant::now(), hottest);
+                state.set_critical(critical_monitor.get_state(Instant::now()));
+            }
+            let step = controller.step(
+                Instant::now(),
+                ControlInput {
+                    temps: &temps,
+                    quiet: quiet_schedule.update(),
+                    throttle_flags,
+                    ..Default::default()
+                },
+                pwm_manager.as_ref(),
+            );
+            let duty = step.duty;
+            if let Some(e) = step.error {
+                error!(fan = cli_args.get_pwm_channel(), duty = duty; "Error setting pwm: {}", e);
+                return Err(e);
+            }
+            debug!(fan = cli_args.get_pwm_channel(), duty = duty; "Fan speed set to {}", duty);
+
+            // keep the fan at the applied duty after exit, next timer run will update it
+            pwm_manager.set_reset_on_drop(false);
+
+            state.set_last_duty(duty);
+            state.set_settings(controller.get_settings(Instant::now(), &cli_args));
+            save_state(&mut state, &state_file);
+            return Ok(());
+        }
+
+        let state_save_period = cli_args.get_state_save_interval();
+        let mut last_state_save = Instant::now();
+
+        let mut scheduler = TickScheduler::new(
+            MonotonicClock,
+            cli_args.get_interval(),
+            cli_args.get_interval_jitter(),
+        );
+        let mut adaptive_interval = cli_args.is_adaptive_interval().then(|| {
+            AdaptiveInterval::new(
+                cli_args.get_min_interval(),
+                cli_args.get_max_interval(),
+                cli_args.get_interval(),
+            )
+        });
+
+        if let Some(watchdog) = notifier.get_watchdog() {
+            let longest_period = if cli_args.is_adaptive_interval() {
+                cli_args.get_max_interval()
+            } else {
+                cli_args.get_interval() + cli_args.get_interval_jitter()
+            };
+            if longest_period >= watchdog / 2 {
+                warn!(
+                    "Update period {:?} too long for the systemd watchdog timeout {:?}",
+                    longest_period, watchdog
+                );
+            }
+        }
+
+        let metrics: SharedMetrics = Arc::new(Mutex::new(Metrics::default()));
+        metrics
+            .lock()
+            .unwrap()
+            .set_curves(controller.get_curves().to_vec());
+        metrics.lock().unwrap().set_profiles(
+            controller.get_profile().map(str::to_string),
+            cli_args
+                .get_profiles()
+                .iter()
+                .map(|p| p.get_name().to_string())
+                .collect(),
+        );
+        if let Some(listen) = cli_args.get_metrics_listen() {
+            start_metrics_exporter(&listen, metrics.clone())?;
+        }
+        let mut rpm_reader = cli_args.get_rpm_file().map(RpmReader::new);
+        let metrics_textfile = cli_args.get_metrics_textfile();
+        let history = cli_args.get_history();
+        let mut history_recorder = history.get_file().map(|path| {
+            let sensors: Vec<String> = controller
+                .get_curves()
+                .iter()
+                .map(|sc| sc.get_sensor().to_string())
+                .collect();
+            HistoryRecorder::new(
+                path,
+                &sensors,
+                history.get_file_size(),
+                history.get_file_count(),
+            )
+        });
+
+        // commands from remote interfaces, applied between two iterations
+        let (command_tx, command_rx) = mpsc::channel::<ControlCommand>();
+
+        let _control_socket = match cli_args.get_control_socket() {
+            Some(path) => match ControlSocket::start(
+                &path,
+                cli_args.get_control_socket_group().as_deref(),
+                metrics.clone(),
+                command_tx.clone(),
+            ) {
+                Ok(control_socket) => Some(control_socket),
+                Err(e) => {
+                    // an optional interface, the fan control runs without it
+                    warn!(
+                        "Error starting control socket {}, remote control disabled: {}",
+                        path.display(),
+                        e
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+
+        #[cfg(feature = "mqtt")]
+        let mqtt_publisher = match cli_args.get_mqtt().get_host() {
+            Some(_) => {
+                let sensors: Vec<String> = controller
+                    .get_curves()
+                    .iter()
+                    .map(|sc| sc.get_sensor().to_string())
+                    .collect();
+                match mqtt::mqtt_publisher::MqttPublisher::start(
+                    &cli_args.get_mqtt(),
+                    &sensors,
+                    rpm_reader.is_some(),
+                    command_tx.clone(),
+                ) {
+                    Ok(publisher) => Some(publisher),
+                    Err(e) => {
+                        error!("Error starting MQTT publisher: {}", e);
+                        return Err(e.into());
+                    }
                 }
             }
+            None => None,
         };
+        #[cfg(not(feature = "mqtt"))]
+        if let Some(host) = cli_args.get_mqtt().get_host() {
+            warn!(
+                "MQTT broker {} ignored, built without the mqtt feature",
+                host
+            );
+        }
+        drop(command_tx);
 
+        let mut last_temps: Vec<u8> = Vec::new();
         // loop until running is set to false
         while running.load(Ordering::SeqCst) {
-            thread::sleep(Duration::from_secs(cli_args.get_sleep_secs()));
+            scheduler.wait_next_tick();
+            let iteration_start = Instant::now();
+            notifier.watchdog(iteration_start);
 
             // raspberry model, can continue from here
-            match read_file_to_string(TEMP_FILE) {
-                Ok(contents) => {
-                    info!("File Contents:\n{}", contents.trim());
-                    match pwm_manager.set_pwm(contents.trim(), &cli_args) {
-                        Ok(()) => {
-                            //info!("pwm set");
-                        }
-                        Err(e) => {
-                            error!("Error setting pwm: {}", e);
-                            return Err(e);
-                        }
-                    }
+            let sensor_error = match read_temps(controller.get_curves()) {
+                Ok(temps) => {
+                    last_temps = temps;
+                    None
                 }
                 Err(e) => {
-                    error!("Error reading file: {}", e);
-                    return Err(e.into());
+                    // failsafe, full speed until the sensors can be read again
+                    error!("Error reading temperatures, fan at full speed: {}", e);
+                    metrics.lock().unwrap().inc_sensor_read_errors();
+                    Some(e)
+                }
+            };
+            // during a sensor error the critical actions and the history see the last reading
+            let temps = last_temps.clone();
+
+            let throttle_flags = throttle_reader
+                .as_ref()
+                .and_then(|reader| throttle_monitor.read(reader));
+            metrics.lock().unwrap().set_throttle_flags(throttle_flags);
+
+            if let Some(critical_monitor) = critical_monitor.as_mut() {
+                let hottest = temps.iter().copied().max().unwrap_or(0);
+                critical_monitor.update(Instant::now(), hottest);
+                state.set_critical(critical_monitor.get_state(Instant::now()));
+            }
+
+            let mut curves_changed = false;
+            for command in command_rx.try_iter() {
+                curves_changed |= matches!(
+                    command,
+                    ControlCommand::SetCurve(_)
+                        | ControlCommand::SetProfile(_)
+                        | ControlCommand::Reload
+                );
+                controller.apply(command, &cli_args);
+            }
+            if curves_changed {
+                let mut metrics = metrics.lock().unwrap();
+                metrics.set_curves(controller.get_curves().to_vec());
+                metrics.set_profile(controller.get_profile().map(str::to_string));
+            }
+
+            let step = controller.step(
+                Instant::now(),
+                ControlInput {
+                    temps: &temps,
+                    sensor_error: sensor_error.is_some(),
+                    quiet: quiet_schedule.update(),
+                    throttle_flags,
+                },
+                pwm_manager.as_ref(),
+            );
+            let (target, duty, mode) = (step.target, step.duty, step.mode);
+            state.set_settings(controller.get_settings(Instant::now(), &cli_args));
+            {
+                let mut metrics = metrics.lock().unwrap();
+                metrics.set_target_duty(target);
+                metrics.set_mode(mode);
+                metrics.set_manual_remaining(controller.get_manual_remaining(Instant::now()));
+            }
+            let applied = match step.error {
+                None => {
+                    debug!(fan = cli_args.get_pwm_channel(), duty = duty; "Fan speed set to {}", duty);
+                    state.set_last_duty(duty);
+                    metrics.lock().unwrap().set_applied_duty(duty);
+                    Some(duty)
+                }
+                Some(e) => {
+                    // keep the previous duty, retried at next iteration
+                    error!(fan = cli_args.get_pwm_channel(), duty = duty; "Error setting pwm: {}", e);
+                    metrics.lock().unwrap().inc_pwm_write_errors();
+                    None
+                }
+            };
+            match &sensor_error {
+                Some(e) => notifier.status(&format!("Failsafe, sensor error: {e}")),
+                None => {
+                    notifier.status(&notify_status(controller.get_curves(), &temps, duty, mode))
+                }
+            }
+            let rpm = rpm_reader.as_mut().and_then(RpmReader::read);
+
+            // nothing to record before the first reading
+            if let Some(recorder) = history_recorder.as_mut().filter(|_| !temps.is_empty()) {
+                let row = HistoryRow::new(
+                    SystemLocalClock.now(),
+                    temps.clone(),
+                    target,
+                    applied,
+                    rpm,
+                    mode,
+                );
+                if let Err(e) = recorder.record(&row) {
+                    warn!("Error writing history file: {}", e);
+                }
+            }
+
+            {
+                let mut metrics = metrics.lock().unwrap();
+                if sensor_error.is_none() {
+                    metrics.set_temperatures(
+                        controller
+                            .get_curves()
+                            .iter()
+                            .zip(&temps)
+                            .map(|(sc, &temp)| (sc.get_sensor().to_string(), temp))
+                            .collect(),
+                    );
+                }
+                metrics.set_rpm(rpm);
+                metrics.set_loop_duration(iteration_start.elapsed());
+
+                if let Some(textfile) = metrics_textfile.as_deref() {
+                    if let Err(e) = metrics.write_textfile(textfile) {
+                        warn!("Error writing metrics file {}: {}", textfile.display(), e);
+                    }
+                }
+
+                #[cfg(feature = "mqtt")]
+                if let Some(publisher) = mqtt_publisher.as_ref() {
+                    publisher.publish(&metrics);
                 }
             }
+
+            // the last reading would look stable, keep the period during a sensor error
+            if let Some(adaptive_interval) = adaptive_interval
+                .as_mut()
+                .filter(|_| sensor_error.is_none())
+            {
+                let step_distance = controller
+                    .get_curves()
+                    .iter()
+                    .zip(&temps)
+                    .map(|(sc, &temp)| sc.get_curve().distance_to_step(temp))
+                    .min()
+                    .unwrap_or(u8::MAX);
+                scheduler.set_period(adaptive_interval.update(
+                    Instant::now(),
+                    &temps,
+                    step_distance,
+                ));
+            }
+
+            if last_state_save.elapsed() >= state_save_period {
+                save_state(&mut state, &state_file);
+                last_state_save = Instant::now();
+            }
         }
+
+        notifier.stopping();
+        save_state(&mut state, &state_file);
         /*if let Ok(device_info) = DeviceInfo::new() {
             debug!(
                 "Device: {} (SoC: {})",
@@ -176,6 +636,470 @@ fn _print_os_info() {
     debug!("Architecture: {:#?}", info.architecture());
 }
 
+// effective curve with the command line settings
+fn show_curve(
+    cli_args: &CliArgs,
+    from: Option<u8>,
+    to: Option<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let controller = FanController::new(cli_args);
+    for sensor_curve in controller.get_curves() {
+        println!("Curve: {sensor_curve}");
+    }
+    warn_curves(&controller);
+    if controller.get_curves().len() > 1 {
+        println!("Policy: {:?}", cli_args.get_curve_policy());
+    }
+    if let Some(profile) = controller.get_profile() {
+        println!("Profile: {profile}");
+    }
+
+    let points = curve_plot::duty_points(&controller, curve_range(&controller, from, to)?);
+    println!();
+    print!("{}", curve_plot::render_chart(&points));
+    println!();
+    print!("{}", curve_plot::render_table(&points));
+    Ok(())
+}
+
+// steps of the effective curves that are likely mistakes, for show-curve and plot
+fn warn_curves(controller: &FanController) {
+    for sensor_curve in controller.get_curves() {
+        for warning in curve_plot::check(sensor_curve.get_curve()) {
+            warn!("Curve {}: {}", sensor_curve.get_sensor(), warning);
+        }
+    }
+}
+
+// temperatures charted, 10°C around the curve steps unless given
+fn curve_range(
+    controller: &FanController,
+    from: Option<u8>,
+    to: Option<u8>,
+) -> Result<RangeInclusive<u8>, Box<dyn std::error::Error>> {
+    let curves: Vec<&FanCurve> = controller
+        .get_curves()
+        .iter()
+        .map(SensorCurve::get_curve)
+        .collect();
+    let default_range = curve_plot::default_range(&curves);
+    let from = from.unwrap_or(*default_range.start());
+    let to = to.unwrap_or(*default_range.end());
+    if from > to {
+        error!("The lowest temperature must not exceed the highest temperature");
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The lowest temperature must not exceed the highest temperature",
+        )
+        .into());
+    }
+    Ok(from..=to)
+}
+
+// SVG of the effective curve and optionally of a recorded history
+fn export_plot(
+    cli_args: &CliArgs,
+    output: &Path,
+    history: Option<&Path>,
+    from: Option<u8>,
+    to: Option<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let controller = FanController::new(cli_args);
+    warn_curves(&controller);
+
+    let points = curve_plot::duty_points(&controller, curve_range(&controller, from, to)?);
+    let mut title: Vec<String> = controller
+        .get_curves()
+        .iter()
+        .map(|sensor_curve| sensor_curve.to_string())
+        .collect();
+    if let Some(profile) = controller.get_profile() {
+        title.push(format!("profile {profile}"));
+    }
+
+    let history = match history {
+        Some(path) => match history_recorder::read(path) {
+            Ok(history) => Some(history),
+            Err(e) => {
+                error!("Error reading history file {}: {}", path.display(), e);
+                return Err(e.into());
+            }
+        },
+        None => None,
+    };
+    let svg = svg_plot::render(
+        &title.join(", "),
+        &points,
+        history
+            .as_ref()
+            .map(|(sensors, rows)| (sensors.as_slice(), rows.as_slice())),
+    );
+    if let Err(e) = fs::write(output, svg) {
+        error!("Error writing {}: {}", output.display(), e);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+// duty cycle series of a recorded history with the current settings, no hardware involved
+fn replay_history(
+    cli_args: &CliArgs,
+    input: &Path,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (sensors, rows) = match history_recorder::read(input) {
+        Ok(history) => history,
+        Err(e) => {
+            error!("Error reading history file {}: {}", input.display(), e);
+            return Err(e.into());
+        }
+    };
+    let columns = match history_replay::sensor_columns(cli_args, &sensors) {
+        Ok(columns) => columns,
+        Err(e) => {
+            error!("{}", e);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, e).into());
+        }
+    };
+    let replayed = history_replay::replay(cli_args, &columns, &rows);
+    write_series(cli_args, &replayed, output)?;
+
+    // comparison with the recorded series, on stderr to keep the CSV output clean
+    eprintln!(
+        "Replayed {} updates: mean duty {:.1}% (recorded {:.1}%), max {:.1}% (recorded {:.1}%)",
+        replayed.len(),
+        mean_duty(&replayed) * 100.0,
+        mean_duty(&rows) * 100.0,
+        max_duty(&replayed) * 100.0,
+        max_duty(&rows) * 100.0
+    );
+    Ok(())
+}
+
+// control loop against a thermal model standing for the sensors and the fan
+fn simulate_thermal(
+    cli_args: &CliArgs,
+    model: ThermalModel,
+    load: &LoadProfile,
+    duration: Duration,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rows =
+        thermal_simulation::simulate(cli_args, model, load, duration, SystemLocalClock.now());
+    write_series(cli_args, &rows, output)?;
+
+    let max_temp = rows
+        .iter()
+        .filter_map(|row| row.get_temps().first().copied())
+        .max()
+        .unwrap_or(0);
+    eprintln!(
+        "Simulated {:?} in {} updates: max temperature {}°C, mean duty {:.1}%, max {:.1}%",
+        duration,
+        rows.len(),
+        max_temp,
+        mean_duty(&rows) * 100.0,
+        max_duty(&rows) * 100.0
+    );
+    Ok(())
+}
+
+// duty cycle series as history CSV, to a file or the standard output
+fn write_series(
+    cli_args: &CliArgs,
+    rows: &[HistoryRow],
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sensors: Vec<String> = cli_args
+        .get_curves()
+        .iter()
+        .map(|sc| sc.get_sensor().to_string())
+        .collect();
+    let mut csv = format!("{}\n", history_recorder::csv_header(&sensors));
+    for row in rows {
+        csv.push_str(&row.to_csv());
+        csv.push('\n');
+    }
+    match output {
+        Some(path) => {
+            if let Err(e) = fs::write(path, csv) {
+                error!("Error writing {}: {}", path.display(), e);
+                return Err(e.into());
+            }
+        }
+        None => print!("{csv}"),
+    }
+    Ok(())
+}
+
+fn mean_duty(rows: &[HistoryRow]) -> f64 {
+    rows.iter().map(HistoryRow::get_target_duty).sum::<f64>() / rows.len().max(1) as f64
+}
+
+fn max_duty(rows: &[HistoryRow]) -> f64 {
+    rows.iter()
+        .map(HistoryRow::get_target_duty)
+        .fold(0.0, f64::max)
+}
+
+// ask the running daemon its status through the control socket
+fn print_status(cli_args: &CliArgs, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(path) = cli_args.get_control_socket() else {
+        error!("The control socket is disabled");
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The control socket is disabled",
+        )
+        .into());
+    };
+
+    let status = match control_socket::send(&path, &Request::Status) {
+        Ok(response) => match response.status {
+            Some(status) => status,
+            None => {
+                let msg = response.error.unwrap_or_default();
+                error!("Status request failed: {}", msg);
+                return Err(io::Error::other(msg).into());
+            }
+        },
+        Err(e) => {
+            error!(
+                "Error connecting to the daemon on {}: {}",
+                path.display(),
+                e
+            );
+            return Err(e.into());
+        }
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+    } else {
+        print!("{status}");
+    }
+    Ok(())
+}
+
+// live dashboard, attached to the daemon or reading the sensors itself
+#[cfg(feature = "tui")]
+fn monitor(
+    cli_args: &CliArgs,
+    standalone: bool,
+    refresh: Duration,
+    manual_duration: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use crate::monitor::tui_monitor::{self, DaemonSource, LocalSource, Monitor, StatusSource};
+
+    let result = if standalone {
+        let mut rpm_reader = cli_args.get_rpm_file().map(RpmReader::new);
+        let mut source = LocalSource::new(cli_args, |curves: &[SensorCurve]| {
+            let temps = read_temps(curves).map_err(|e| e.to_string())?;
+            Ok((temps, rpm_reader.as_mut().and_then(RpmReader::read)))
+        });
+        let mut monitor = Monitor::new(source.name(), manual_duration);
+        tui_monitor::run(&mut source, &mut monitor, refresh)
+    } else {
+        let Some(path) = cli_args.get_control_socket() else {
+            error!("The control socket is disabled, use --standalone");
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The control socket is disabled",
+            )
+            .into());
+        };
+        let mut source = DaemonSource::new(&path);
+        let mut monitor = Monitor::new(source.name(), manual_duration);
+        tui_monitor::run(&mut source, &mut monitor, refresh)
+    };
+    if let Err(e) = result {
+        error!("Terminal error: {}", e);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "tui"))]
+fn monitor(
+    _cli_args: &CliArgs,
+    _standalone: bool,
+    _refresh: Duration,
+    _manual_duration: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    error!("Monitor unavailable, built without the tui feature");
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Monitor unavailable, built without the tui feature",
+    )
+    .into())
+}
+
+#[cfg(feature = "prometheus")]
+fn start_metrics_exporter(
+    listen: &str,
+    metrics: SharedMetrics,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match metrics::metrics_exporter::start(listen, metrics) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("Error starting metrics exporter on {}: {}", listen, e);
+            Err(e.into())
+        }
+    }
+}
+
+#[cfg(not(feature = "prometheus"))]
+fn start_metrics_exporter(
+    listen: &str,
+    _metrics: SharedMetrics,
+) -> Result<(), Box<dyn std::error::Error>> {
+    warn!(
+        "Metrics exporter on {} ignored, built without the prometheus feature",
+        listen
+    );
+    Ok(())
+}
+
+// warn when the kernel throttles before the curve reaches full speed
+fn check_trip_points(sensor_curve: &SensorCurve) {
+    let Some(zone_dir) = Path::new(sensor_curve.get_sensor()).parent() else {
+        return;
+    };
+    let trips = match trip_points::read(zone_dir) {
+        Ok(trips) => trips,
+        Err(e) => {
+            debug!("No trip points in {}: {}", zone_dir.display(), e);
+            return;
+        }
+    };
+    let Some(throttle) = trip_points::throttle_temp(&trips) else {
+        return;
+    };
+
+    match trip_points::full_speed_temp(sensor_curve.get_curve()) {
+        Some(full) if full < throttle => {}
+        Some(full) => warn!(
+            "Curve of {} reaches full speed at {}°C, the kernel throttles from {}°C",
+            sensor_curve.get_sensor(),
+            full,
+            throttle
+        ),
+        None => warn!(
+            "Curve of {} never reaches full speed, the kernel throttles from {}°C",
+            sensor_curve.get_sensor(),
+            throttle
+        ),
+    }
+}
+
+// e.g. `thermal_zone0 52°C, duty 40% (auto)` for systemctl status
+fn notify_status(curves: &[SensorCurve], temps: &[u8], duty: f64, mode: FanMode) -> String {
+    let temps: Vec<String> = curves
+        .iter()
+        .zip(temps)
+        .map(|(sc, temp)| format!("{} {}°C", fan_curve::sensor_name(sc.get_sensor()), temp))
+        .collect();
+    format!("{}, duty {:.0}% ({})", temps.join(", "), duty * 100.0, mode)
+}
+
+// the firmware is already limiting the clock, help it cooling down
+fn build_critical_monitor(cli_args: &CliArgs) -> Option<CriticalMonitor<Box<dyn ActionExecutor>>> {
+    cli_args.get_critical_temp().map(|threshold| {
+        // a dry run leaves the system alone too
+        let executor: Box<dyn ActionExecutor> =
+            if cli_args.is_critical_dry_run() || cli_args.is_dry_run() {
+                Box::new(DryRunExecutor::default())
+            } else {
+                Box::new(SystemExecutor)
+            };
+        CriticalMonitor::new(
+            threshold,
+            cli_args.get_critical_grace(),
+            cli_args.get_critical_cooldown(),
+            cli_args.get_critical_actions(),
+            executor,
+        )
+    })
+}
+
+// fan speed from the tachometer file, none when it can't be read
+// fan tachometer, a failure is logged once and the read retried at the next update
+struct RpmReader {
+    path: PathBuf,
+    failing: bool,
+}
+
+impl RpmReader {
+    fn new(path: PathBuf) -> Self {
+        RpmReader {
+            path,
+            failing: false,
+        }
+    }
+
+    fn read(&mut self) -> Option<u32> {
+        let rpm = fs::read_to_string(&self.path).and_then(|contents| {
+            contents.trim().parse().map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("Invalid RPM: {e}"))
+            })
+        });
+        match rpm {
+            Ok(rpm) => {
+                if std::mem::take(&mut self.failing) {
+                    info!("RPM file {} readable again", self.path.display());
+                }
+                Some(rpm)
+            }
+            Err(e) if self.failing => {
+                debug!("Error reading RPM file {}: {}", self.path.display(), e);
+                None
+            }
+            Err(e) => {
+                warn!(
+                    "Error reading RPM file {}, retrying: {}",
+                    self.path.display(),
+                    e
+                );
+                self.failing = true;
+                None
+            }
+        }
+    }
+}
+
+// a failed save only loses the state for the next start, keep running
+fn save_state(state: &mut ControllerState, state_file: &Path) {
+    if let Err(e) = state.save(state_file) {
+        warn!("Error saving state file {}: {}", state_file.display(), e);
+    }
+}
+
+// read the temperature of every curve sensor, in curves order
+fn read_temps(curves: &[SensorCurve]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut temps: Vec<u8> = Vec::with_capacity(curves.len());
+    for sensor_curve in curves {
+        match read_file_to_string(sensor_curve.get_sensor()) {
+            Ok(contents) => {
+                let temp = parse_temp_string(contents.trim())?;
+                info!(
+                    sensor = sensor_curve.get_sensor(), temp_c = temp;
+                    "File Contents ({}):\n{}",
+                    sensor_curve.get_sensor(),
+                    contents.trim()
+                );
+                temps.push(temp);
+            }
+            Err(e) => {
+                error!(
+                    sensor = sensor_curve.get_sensor();
+                    "Error reading file {}: {}", sensor_curve.get_sensor(), e
+                );
+                return Err(e.into());
+            }
+        }
+    }
+    Ok(temps)
+}
+
 fn read_file_to_string(filename: &str) -> Result<String, io::Error> {
     /* let mut file = File::open(filename)?;
     let mut contents = String::new();
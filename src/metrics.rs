@@ -0,0 +1,429 @@
+pub mod fan_metrics {
+    use crate::controller::fan_controller::FanMode;
+    use crate::curve::fan_curve::SensorCurve;
+    use crate::throttle::throttle_status::{self, ThrottleFlags};
+    use std::fmt::Write;
+    use std::fs;
+    use std::io::{self};
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Values of the last control loop iteration, shared with the exporters.
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct Metrics {
+        temperatures: Vec<(String, u8)>,
+        target_duty: f64,
+        applied_duty: f64,
+        rpm: Option<u32>,
+        throttle_flags: Option<ThrottleFlags>,
+        mode: FanMode,
+        manual_remaining: Option<Duration>,
+        curves: Vec<SensorCurve>,
+        profile: Option<String>,
+        profiles: Vec<String>,
+        loop_duration: Duration,
+        sensor_read_errors: u64,
+        pwm_write_errors: u64,
+    }
+
+    pub type SharedMetrics = Arc<Mutex<Metrics>>;
+
+    impl Metrics {
+        pub fn set_temperatures(&mut self, temperatures: Vec<(String, u8)>) {
+            self.temperatures = temperatures;
+        }
+
+        pub fn set_target_duty(&mut self, duty: f64) {
+            self.target_duty = duty;
+        }
+
+        pub fn set_applied_duty(&mut self, duty: f64) {
+            self.applied_duty = duty;
+        }
+
+        pub fn set_rpm(&mut self, rpm: Option<u32>) {
+            self.rpm = rpm;
+        }
+
+        pub fn set_throttle_flags(&mut self, flags: Option<ThrottleFlags>) {
+            self.throttle_flags = flags;
+        }
+
+        pub fn set_mode(&mut self, mode: FanMode) {
+            self.mode = mode;
+        }
+
+        pub fn set_manual_remaining(&mut self, remaining: Option<Duration>) {
+            self.manual_remaining = remaining;
+        }
+
+        pub fn set_curves(&mut self, curves: Vec<SensorCurve>) {
+            self.curves = curves;
+        }
+
+        pub fn set_profile(&mut self, profile: Option<String>) {
+            self.profile = profile;
+        }
+
+        /// Active profile and the names of the available ones.
+        pub fn set_profiles(&mut self, profile: Option<String>, profiles: Vec<String>) {
+            self.profile = profile;
+            self.profiles = profiles;
+        }
+
+        pub fn set_loop_duration(&mut self, duration: Duration) {
+            self.loop_duration = duration;
+        }
+
+        pub fn get_temperatures(&self) -> &[(String, u8)] {
+            &self.temperatures
+        }
+
+        pub fn get_target_duty(&self) -> f64 {
+            self.target_duty
+        }
+
+        pub fn get_applied_duty(&self) -> f64 {
+            self.applied_duty
+        }
+
+        pub fn get_rpm(&self) -> Option<u32> {
+            self.rpm
+        }
+
+        pub fn get_mode(&self) -> FanMode {
+            self.mode
+        }
+
+        pub fn get_manual_remaining(&self) -> Option<Duration> {
+            self.manual_remaining
+        }
+
+        pub fn get_curves(&self) -> &[SensorCurve] {
+            &self.curves
+        }
+
+        pub fn get_profile(&self) -> Option<&str> {
+            self.profile.as_deref()
+        }
+
+        pub fn get_profiles(&self) -> &[String] {
+            &self.profiles
+        }
+
+        pub fn inc_sensor_read_errors(&mut self) {
+            self.sensor_read_errors += 1;
+        }
+
+        pub fn inc_pwm_write_errors(&mut self) {
+            self.pwm_write_errors += 1;
+        }
+
+        /// Render the metrics in the Prometheus text exposition format.
+        pub fn render(&self) -> String {
+            let mut out = String::new();
+
+            let header = |out: &mut String, name: &str, kind: &str, help: &str| {
+                let _ = writeln!(out, "# HELP {name} {help}");
+                let _ = writeln!(out, "# TYPE {name} {kind}");
+            };
+
+            header(
+                &mut out,
+                "rpi_fan_temperature_celsius",
+                "gauge",
+                "Temperature read from the sensor.",
+            );
+            for (sensor, temp) in &self.temperatures {
+                let _ = writeln!(
+                    out,
+                    "rpi_fan_temperature_celsius{{sensor=\"{}\"}} {}",
+                    escape_label(sensor),
+                    temp
+                );
+            }
+
+            header(
+                &mut out,
+                "rpi_fan_target_duty_ratio",
+                "gauge",
+                "Duty cycle requested by the curves, from 0 to 1.",
+            );
+            let _ = writeln!(out, "rpi_fan_target_duty_ratio {}", self.target_duty);
+
+            header(
+                &mut out,
+                "rpi_fan_applied_duty_ratio",
+                "gauge",
+                "Duty cycle applied to the PWM channel, from 0 to 1.",
+            );
+            let _ = writeln!(out, "rpi_fan_applied_duty_ratio {}", self.applied_duty);
+
+            if let Some(rpm) = self.rpm {
+                header(
+                    &mut out,
+                    "rpi_fan_rpm",
+                    "gauge",
+                    "Fan speed in revolutions per minute.",
+                );
+                let _ = writeln!(out, "rpi_fan_rpm {rpm}");
+            }
+
+            if let Some(flags) = self.throttle_flags {
+                header(
+                    &mut out,
+                    "rpi_fan_throttle_active",
+                    "gauge",
+                    "Raspberry Pi firmware throttling flag currently set.",
+                );
+                for (flag, name) in throttle_status::FLAGS {
+                    let _ = writeln!(
+                        out,
+                        "rpi_fan_throttle_active{{flag=\"{name}\"}} {}",
+                        u8::from(flags.is_set(flag))
+                    );
+                }
+                header(
+                    &mut out,
+                    "rpi_fan_throttle_occurred",
+                    "gauge",
+                    "Raspberry Pi firmware throttling flag set since boot.",
+                );
+                for (flag, name) in throttle_status::FLAGS {
+                    let _ = writeln!(
+                        out,
+                        "rpi_fan_throttle_occurred{{flag=\"{name}\"}} {}",
+                        u8::from(flags.is_set(flag << 16))
+                    );
+                }
+            }
+
+            header(
+                &mut out,
+                "rpi_fan_loop_duration_seconds",
+                "gauge",
+                "Duration of the last control loop iteration.",
+            );
+            let _ = writeln!(
+                out,
+                "rpi_fan_loop_duration_seconds {}",
+                self.loop_duration.as_secs_f64()
+            );
+
+            header(
+                &mut out,
+                "rpi_fan_sensor_read_errors_total",
+                "counter",
+                "Failed temperature sensor reads.",
+            );
+            let _ = writeln!(
+                out,
+                "rpi_fan_sensor_read_errors_total {}",
+                self.sensor_read_errors
+            );
+
+            header(
+                &mut out,
+                "rpi_fan_pwm_write_errors_total",
+                "counter",
+                "Failed PWM channel updates.",
+            );
+            let _ = writeln!(
+                out,
+                "rpi_fan_pwm_write_errors_total {}",
+                self.pwm_write_errors
+            );
+
+            out
+        }
+
+        /// Write the metrics for the node_exporter textfile collector. The file is written
+        /// with a temporary name and renamed, so the collector never reads a partial file.
+        pub fn write_textfile(&self, path: &Path) -> Result<(), io::Error> {
+            // the collector only reads *.prom files, the temporary one is ignored
+            let mut tmp_path = path.as_os_str().to_owned();
+            tmp_path.push(".tmp");
+            fs::write(&tmp_path, self.render())?;
+            fs::rename(&tmp_path, path)
+        }
+    }
+
+    // label values escape backslash, double quote and line feed
+    fn escape_label(value: &str) -> String {
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_render() {
+            let mut metrics = Metrics::default();
+            metrics.set_temperatures(vec![
+                ("/sys/class/thermal/thermal_zone0/temp".to_string(), 55),
+                ("/tmp/\"nvme\"".to_string(), 40),
+            ]);
+            metrics.set_target_duty(0.5);
+            metrics.set_applied_duty(0.42);
+            metrics.set_loop_duration(Duration::from_millis(3));
+            metrics.inc_sensor_read_errors();
+            metrics.inc_pwm_write_errors();
+            metrics.inc_pwm_write_errors();
+
+            let out = metrics.render();
+            assert!(out.contains(
+                "rpi_fan_temperature_celsius{sensor=\"/sys/class/thermal/thermal_zone0/temp\"} 55\n"
+            ));
+            assert!(out.contains("rpi_fan_temperature_celsius{sensor=\"/tmp/\\\"nvme\\\"\"} 40\n"));
+            assert!(out.contains("# TYPE rpi_fan_target_duty_ratio gauge\n"));
+            assert!(out.contains("rpi_fan_target_duty_ratio 0.5\n"));
+            assert!(out.contains("rpi_fan_applied_duty_ratio 0.42\n"));
+            assert!(out.contains("rpi_fan_loop_duration_seconds 0.003\n"));
+            assert!(out.contains("# TYPE rpi_fan_sensor_read_errors_total counter\n"));
+            assert!(out.contains("rpi_fan_sensor_read_errors_total 1\n"));
+            assert!(out.contains("rpi_fan_pwm_write_errors_total 2\n"));
+            // no tachometer configured
+            assert!(!out.contains("rpi_fan_rpm"));
+
+            metrics.set_rpm(Some(3200));
+            assert!(metrics.render().contains("rpi_fan_rpm 3200\n"));
+
+            // throttling flags only on a Raspberry Pi
+            assert!(!out.contains("rpi_fan_throttle"));
+            metrics.set_throttle_flags(Some(ThrottleFlags::new(0x50001)));
+            let out = metrics.render();
+            assert!(out.contains("rpi_fan_throttle_active{flag=\"under_voltage\"} 1\n"));
+            assert!(out.contains("rpi_fan_throttle_active{flag=\"throttled\"} 0\n"));
+            assert!(out.contains("rpi_fan_throttle_occurred{flag=\"throttled\"} 1\n"));
+        }
+
+        #[test]
+        fn test_write_textfile() {
+            let dir =
+                std::env::temp_dir().join(format!("rpi-temp-fan-pwm-prom-{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("rpi_fan.prom");
+
+            let mut metrics = Metrics::default();
+            metrics.set_applied_duty(0.3);
+            metrics.write_textfile(&path).unwrap();
+            metrics.set_applied_duty(0.6);
+            metrics.write_textfile(&path).unwrap();
+
+            assert_eq!(fs::read_to_string(&path).unwrap(), metrics.render());
+            // only the final file is left in the collector directory
+            assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+#[cfg(feature = "prometheus")]
+pub mod metrics_exporter {
+    use super::fan_metrics::SharedMetrics;
+    use log::{debug, error, info};
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Serve `GET /metrics` on a background thread, returning the bound address.
+    pub fn start(listen: &str, metrics: SharedMetrics) -> Result<SocketAddr, io::Error> {
+        let listener = TcpListener::bind(listen)?;
+        let addr = listener.local_addr()?;
+        info!("Metrics exporter listening on http://{}/metrics", addr);
+
+        thread::Builder::new()
+            .name("metrics-exporter".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            if let Err(e) = handle_connection(stream, &metrics) {
+                                debug!("Metrics request failed: {}", e);
+                            }
+                        }
+                        Err(e) => error!("Metrics exporter accept failed: {}", e),
+                    }
+                }
+            })?;
+        Ok(addr)
+    }
+
+    fn handle_connection(stream: TcpStream, metrics: &SharedMetrics) -> Result<(), io::Error> {
+        // a stuck client must not block the following scrapes
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+        let mut reader = BufReader::new(&stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // drain headers until the empty line
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 2 {
+            line.clear();
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let response = match (parts.next(), parts.next()) {
+            (Some("GET"), Some("/metrics")) => {
+                let body = metrics.lock().unwrap().render();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            }
+            _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_string(),
+        };
+
+        let mut stream = &stream;
+        stream.write_all(response.as_bytes())?;
+        stream.flush()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::metrics::fan_metrics::Metrics;
+        use std::io::Read;
+        use std::sync::{Arc, Mutex};
+
+        fn get(addr: SocketAddr, path: &str) -> String {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        }
+
+        #[test]
+        fn test_scrape_localhost() {
+            let metrics: SharedMetrics = Arc::new(Mutex::new(Metrics::default()));
+            let addr = start("127.0.0.1:0", metrics.clone()).unwrap();
+
+            metrics.lock().unwrap().set_applied_duty(0.75);
+            metrics
+                .lock()
+                .unwrap()
+                .set_temperatures(vec![("cpu".to_string(), 61)]);
+
+            let response = get(addr, "/metrics");
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(response.contains("rpi_fan_applied_duty_ratio 0.75\n"));
+            assert!(response.contains("rpi_fan_temperature_celsius{sensor=\"cpu\"} 61\n"));
+
+            // values are read at each scrape
+            metrics.lock().unwrap().inc_sensor_read_errors();
+            assert!(get(addr, "/metrics").contains("rpi_fan_sensor_read_errors_total 1\n"));
+
+            assert!(get(addr, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+        }
+    }
+}
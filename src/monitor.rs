@@ -0,0 +1,622 @@
+pub mod tui_monitor {
+    use crate::cli_arguments::cli_args::CliArgsTrait;
+    use crate::control::control_command::ControlCommand;
+    use crate::controller::fan_controller::{ControlInput, FanController};
+    use crate::curve::fan_curve::{self, FanCurve, SensorCurve};
+    use crate::plot::curve_plot;
+    use crate::pwm::pwm_manager::NullPwmManager;
+    use crate::quiet::quiet_schedule::{QuietSchedule, SystemLocalClock};
+    use crate::socket::control_socket::{self, Request, SensorTemperature, Status};
+    use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+    use ratatui::layout::{Constraint, Layout, Rect};
+    use ratatui::style::{Color, Style};
+    use ratatui::symbols::Marker;
+    use ratatui::text::Line;
+    use ratatui::widgets::{
+        Axis, Block, Chart, Dataset, Gauge, GraphType, Paragraph, Row, Sparkline, Table,
+    };
+    use ratatui::{DefaultTerminal, Frame};
+    use std::collections::VecDeque;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+
+    /// Updates kept for the sparklines, wider than most terminals.
+    const HISTORY_LEN: usize = 300;
+
+    const HELP: &str = "q quit  p next profile  1-9 manual 10-90%  0 manual 100%  a auto";
+
+    /// Where the monitor gets its status and sends the commands of the keyboard.
+    pub trait StatusSource {
+        fn name(&self) -> String;
+        fn status(&mut self) -> Result<Status, String>;
+        fn send(&mut self, command: ControlCommand) -> Result<(), String>;
+    }
+
+    /// Running daemon, through its control socket.
+    pub struct DaemonSource {
+        path: PathBuf,
+    }
+
+    impl DaemonSource {
+        pub fn new(path: &Path) -> Self {
+            DaemonSource {
+                path: path.to_path_buf(),
+            }
+        }
+
+        fn request(&self, request: Request) -> Result<control_socket::Response, String> {
+            let response = control_socket::send(&self.path, &request)
+                .map_err(|e| format!("Daemon unreachable on {}: {}", self.path.display(), e))?;
+            match response.error.clone() {
+                Some(e) if !response.ok => Err(e),
+                _ => Ok(response),
+            }
+        }
+    }
+
+    impl StatusSource for DaemonSource {
+        fn name(&self) -> String {
+            format!("daemon {}", self.path.display())
+        }
+
+        fn status(&mut self) -> Result<Status, String> {
+            self.request(Request::Status)?
+                .status
+                .ok_or_else(|| "Empty status".to_string())
+        }
+
+        fn send(&mut self, command: ControlCommand) -> Result<(), String> {
+            self.request(command.into()).map(|_| ())
+        }
+    }
+
+    /// Sensors read by the monitor itself, through the configured curves and a PWM
+    /// backend without hardware: the fan isn't driven.
+    pub struct LocalSource<'a, C: CliArgsTrait, F> {
+        cli_args: &'a C,
+        controller: FanController,
+        quiet_schedule: QuietSchedule<SystemLocalClock>,
+        pwm_manager: NullPwmManager,
+        started: Instant,
+        // temperatures in curves order and fan speed
+        read: F,
+    }
+
+    impl<'a, C, F> LocalSource<'a, C, F>
+    where
+        C: CliArgsTrait,
+        F: FnMut(&[SensorCurve]) -> Result<(Vec<u8>, Option<u32>), String>,
+    {
+        pub fn new(cli_args: &'a C, read: F) -> Self {
+            LocalSource {
+                cli_args,
+                controller: FanController::new(cli_args),
+                quiet_schedule: QuietSchedule::new(SystemLocalClock, cli_args.get_quiet_profiles()),
+                pwm_manager: NullPwmManager::default(),
+                started: Instant::now(),
+                read,
+            }
+        }
+    }
+
+    impl<C, F> StatusSource for LocalSource<'_, C, F>
+    where
+        C: CliArgsTrait,
+        F: FnMut(&[SensorCurve]) -> Result<(Vec<u8>, Option<u32>), String>,
+    {
+        fn name(&self) -> String {
+            "standalone, fan not driven".to_string()
+        }
+
+        fn status(&mut self) -> Result<Status, String> {
+            let (temps, rpm) = (self.read)(self.controller.get_curves())?;
+            let now = Instant::now();
+            let step = self.controller.step(
+                now,
+                ControlInput {
+                    temps: &temps,
+                    quiet: self.quiet_schedule.update(),
+                    ..Default::default()
+                },
+                &self.pwm_manager,
+            );
+            if let Some(e) = step.error {
+                return Err(e.to_string());
+            }
+
+            Ok(Status {
+                temperatures: self
+                    .controller
+                    .get_curves()
+                    .iter()
+                    .zip(&temps)
+                    .map(|(sc, &celsius)| SensorTemperature {
+                        sensor: sc.get_sensor().to_string(),
+                        celsius,
+                    })
+                    .collect(),
+                curves: self
+                    .controller
+                    .get_curves()
+                    .iter()
+                    .map(|sc| sc.to_string())
+                    .collect(),
+                profile: self.controller.get_profile().map(str::to_string),
+                profiles: self
+                    .cli_args
+                    .get_profiles()
+                    .iter()
+                    .map(|p| p.get_name().to_string())
+                    .collect(),
+                target_duty: step.target,
+                applied_duty: self.pwm_manager.get_duty(),
+                rpm,
+                mode: step.mode.to_string(),
+                manual_remaining_secs: self
+                    .controller
+                    .get_manual_remaining(now)
+                    .map(|d| d.as_secs()),
+                uptime_secs: self.started.elapsed().as_secs(),
+            })
+        }
+
+        fn send(&mut self, command: ControlCommand) -> Result<(), String> {
+            self.controller.apply(command, self.cli_args);
+            Ok(())
+        }
+    }
+
+    /// What a key asks for.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Action {
+        None,
+        Quit,
+        Send(ControlCommand),
+    }
+
+    /// Screen state: last status, sparkline series and the outcome of the last command.
+    #[derive(Debug, Default)]
+    pub struct Monitor {
+        source: String,
+        status: Option<Status>,
+        error: Option<String>,
+        message: Option<String>,
+        duty_history: VecDeque<u64>,
+        temp_history: VecDeque<u64>,
+        manual_duration: Duration,
+    }
+
+    impl Monitor {
+        /// Manual speeds set from the keyboard last `manual_duration`.
+        pub fn new(source: String, manual_duration: Duration) -> Self {
+            Monitor {
+                source,
+                manual_duration,
+                ..Default::default()
+            }
+        }
+
+        pub fn update(&mut self, status: Result<Status, String>) {
+            match status {
+                Ok(status) => {
+                    push(
+                        &mut self.duty_history,
+                        (status.applied_duty * 100.0).round() as u64,
+                    );
+                    let hottest = status.temperatures.iter().map(|t| t.celsius).max();
+                    push(&mut self.temp_history, u64::from(hottest.unwrap_or(0)));
+                    self.status = Some(status);
+                    self.error = None;
+                }
+                Err(e) => self.error = Some(e),
+            }
+        }
+
+        /// Result of a command sent for a key, shown in the footer.
+        pub fn set_message(&mut self, message: String) {
+            self.message = Some(message);
+        }
+
+        pub fn handle_key(&self, code: KeyCode, modifiers: KeyModifiers) -> Action {
+            match code {
+                KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => Action::Quit,
+                KeyCode::Char('a') => Action::Send(ControlCommand::ClearManual),
+                KeyCode::Char('p') => match self.next_profile() {
+                    Some(profile) => Action::Send(ControlCommand::SetProfile(profile)),
+                    None => Action::None,
+                },
+                KeyCode::Char(c) if c.is_ascii_digit() => {
+                    let speed = match c.to_digit(10).unwrap_or(0) as u8 {
+                        0 => 100,
+                        tens => tens * 10,
+                    };
+                    Action::Send(ControlCommand::SetManualFor(speed, self.manual_duration))
+                }
+                _ => Action::None,
+            }
+        }
+
+        // the profile of the source after the active one, the first one when none is active
+        fn next_profile(&self) -> Option<String> {
+            let status = self.status.as_ref()?;
+            let profiles = &status.profiles;
+            let next = match status
+                .profile
+                .as_deref()
+                .and_then(|c| profiles.iter().position(|p| p == c))
+            {
+                Some(i) => (i + 1) % profiles.len(),
+                None => 0,
+            };
+            profiles.get(next).cloned()
+        }
+
+        pub fn draw(&self, frame: &mut Frame) {
+            let [header, main, sparklines, footer] = Layout::vertical([
+                Constraint::Length(4),
+                Constraint::Min(10),
+                Constraint::Length(8),
+                Constraint::Length(2),
+            ])
+            .areas(frame.area());
+            let [readings, curve] =
+                Layout::horizontal([Constraint::Length(36), Constraint::Min(30)]).areas(main);
+
+            self.draw_header(frame, header);
+            self.draw_readings(frame, readings);
+            self.draw_curve(frame, curve);
+
+            let [duty, temp] =
+                Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .areas(sparklines);
+            let duty_history: Vec<u64> = self.duty_history.iter().copied().collect();
+            frame.render_widget(
+                Sparkline::default()
+                    .block(Block::bordered().title("Duty %"))
+                    .data(&duty_history[duty_history.len().saturating_sub(duty.width as usize)..])
+                    .max(100)
+                    .style(Style::default().fg(Color::Cyan)),
+                duty,
+            );
+            let temp_history: Vec<u64> = self.temp_history.iter().copied().collect();
+            frame.render_widget(
+                Sparkline::default()
+                    .block(Block::bordered().title("Hottest °C"))
+                    .data(&temp_history[temp_history.len().saturating_sub(temp.width as usize)..])
+                    .max(100)
+                    .style(Style::default().fg(Color::Red)),
+                temp,
+            );
+
+            let mut lines = vec![Line::from(HELP)];
+            match (&self.error, &self.message) {
+                (Some(e), _) => lines.push(Line::styled(e.as_str(), Color::Red)),
+                (None, Some(message)) => lines.push(Line::from(message.as_str())),
+                (None, None) => {}
+            }
+            frame.render_widget(Paragraph::new(lines), footer);
+        }
+
+        fn draw_header(&self, frame: &mut Frame, area: Rect) {
+            let block = Block::bordered().title(format!(" rpi-temp-fan-pwm — {} ", self.source));
+            let Some(status) = &self.status else {
+                frame.render_widget(
+                    Paragraph::new("Waiting for the first reading").block(block),
+                    area,
+                );
+                return;
+            };
+            let mode = match status.manual_remaining_secs {
+                Some(secs) => format!("{} ({}s left)", status.mode, secs),
+                None => status.mode.clone(),
+            };
+            let rpm = status
+                .rpm
+                .map_or_else(|| "n/a".to_string(), |rpm| rpm.to_string());
+            let uptime = status.uptime_secs;
+            let lines = vec![
+                Line::from(format!(
+                    "Mode: {}   Profile: {}   RPM: {}",
+                    mode,
+                    status.profile.as_deref().unwrap_or("none"),
+                    rpm
+                )),
+                Line::from(format!(
+                    "Target: {:.0}%   Uptime: {}h {:02}m {:02}s",
+                    status.target_duty * 100.0,
+                    uptime / 3600,
+                    uptime / 60 % 60,
+                    uptime % 60
+                )),
+            ];
+            frame.render_widget(Paragraph::new(lines).block(block), area);
+        }
+
+        fn draw_readings(&self, frame: &mut Frame, area: Rect) {
+            let [temps, duty] =
+                Layout::vertical([Constraint::Min(3), Constraint::Length(3)]).areas(area);
+            let rows: Vec<Row> = self
+                .status
+                .iter()
+                .flat_map(|s| &s.temperatures)
+                .map(|t| {
+                    Row::new(vec![
+                        fan_curve::sensor_name(&t.sensor),
+                        format!("{:>3}°C", t.celsius),
+                    ])
+                })
+                .collect();
+            frame.render_widget(
+                Table::new(rows, [Constraint::Min(20), Constraint::Length(6)])
+                    .block(Block::bordered().title("Temperatures")),
+                temps,
+            );
+            let applied = self.status.as_ref().map_or(0.0, |s| s.applied_duty);
+            frame.render_widget(
+                Gauge::default()
+                    .block(Block::bordered().title("Duty"))
+                    .gauge_style(Style::default().fg(Color::Cyan))
+                    .ratio(applied.clamp(0.0, 1.0))
+                    .label(format!("{:.0}%", applied * 100.0)),
+                duty,
+            );
+        }
+
+        // each curve, with its sensor temperature and curve speed marked
+        fn draw_curve(&self, frame: &mut Frame, area: Rect) {
+            let curves: Vec<SensorCurve> = self
+                .status
+                .iter()
+                .flat_map(|s| &s.curves)
+                .filter_map(|c| c.parse().ok())
+                .collect();
+            let range = curve_plot::default_range(
+                &curves
+                    .iter()
+                    .map(SensorCurve::get_curve)
+                    .collect::<Vec<_>>(),
+            );
+            let (from, to) = (f64::from(*range.start()), f64::from(*range.end()));
+
+            let lines: Vec<Vec<(f64, f64)>> = curves
+                .iter()
+                .map(|sc| curve_line(sc.get_curve(), from, to))
+                .collect();
+            // target duty of the source, with the profile, quiet profile or manual speed applied
+            let points: Vec<[(f64, f64); 1]> = self
+                .status
+                .iter()
+                .flat_map(|s| s.temperatures.iter().map(|t| (t.celsius, s.target_duty)))
+                .take(curves.len())
+                .map(|(celsius, duty)| [(f64::from(celsius), duty * 100.0)])
+                .collect();
+
+            let mut datasets: Vec<Dataset> = lines
+                .iter()
+                .zip(&curves)
+                .map(|(line, sc)| {
+                    Dataset::default()
+                        .name(fan_curve::sensor_name(sc.get_sensor()))
+                        .marker(Marker::Braille)
+                        .graph_type(GraphType::Line)
+                        .style(Style::default().fg(Color::Cyan))
+                        .data(line)
+                })
+                .collect();
+            datasets.extend(points.iter().map(|point| {
+                Dataset::default()
+                    .marker(Marker::Block)
+                    .graph_type(GraphType::Scatter)
+                    .style(Style::default().fg(Color::Yellow))
+                    .data(point)
+            }));
+
+            let chart = Chart::new(datasets)
+                .block(Block::bordered().title("Curve"))
+                .x_axis(Axis::default().bounds([from, to]).labels([
+                    format!("{from:.0}°C"),
+                    format!("{:.0}°C", (from + to) / 2.0),
+                    format!("{to:.0}°C"),
+                ]))
+                .y_axis(
+                    Axis::default()
+                        .bounds([0.0, 100.0])
+                        .labels(["0%", "50%", "100%"]),
+                );
+            frame.render_widget(chart, area);
+        }
+    }
+
+    fn push(history: &mut VecDeque<u64>, value: u64) {
+        if history.len() == HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(value);
+    }
+
+    // curve steps, flat from `from` to the first one and from the last one to `to`
+    fn curve_line(curve: &FanCurve, from: f64, to: f64) -> Vec<(f64, f64)> {
+        let steps = curve
+            .get_temp_step()
+            .iter()
+            .zip(curve.get_speed_step())
+            .map(|(&t, &s)| (f64::from(t), f64::from(s)));
+        let first = curve.get_speed_step().first().copied().unwrap_or(0);
+        let last = curve.get_speed_step().last().copied().unwrap_or(100);
+        std::iter::once((from, f64::from(first)))
+            .chain(steps)
+            .chain(std::iter::once((to, f64::from(last))))
+            .collect()
+    }
+
+    /// Refresh the screen every `refresh` until quit, restoring the terminal on exit.
+    pub fn run(
+        source: &mut impl StatusSource,
+        monitor: &mut Monitor,
+        refresh: Duration,
+    ) -> Result<(), io::Error> {
+        let mut terminal = ratatui::init();
+        let result = event_loop(&mut terminal, source, monitor, refresh);
+        ratatui::restore();
+        result
+    }
+
+    fn event_loop(
+        terminal: &mut DefaultTerminal,
+        source: &mut impl StatusSource,
+        monitor: &mut Monitor,
+        refresh: Duration,
+    ) -> Result<(), io::Error> {
+        let mut next_refresh = Instant::now();
+        loop {
+            if Instant::now() >= next_refresh {
+                monitor.update(source.status());
+                next_refresh = Instant::now() + refresh;
+            }
+            terminal.draw(|frame| monitor.draw(frame))?;
+
+            if !event::poll(next_refresh.saturating_duration_since(Instant::now()))? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match monitor.handle_key(key.code, key.modifiers) {
+                Action::Quit => return Ok(()),
+                Action::Send(command) => {
+                    let description = format!("{command:?}");
+                    match source.send(command) {
+                        Ok(_) => monitor.set_message(format!("Sent {description}")),
+                        Err(e) => monitor.set_message(format!("{description} failed: {e}")),
+                    }
+                    // show the effect right away
+                    next_refresh = Instant::now();
+                }
+                Action::None => {}
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::cli_arguments::cli_args::{MockArgs, MockCliArgsTrait};
+        use ratatui::backend::TestBackend;
+        use ratatui::Terminal;
+
+        fn cli_mock() -> MockCliArgsTrait {
+            MockArgs {
+                curves: vec![SensorCurve::new(
+                    "/sys/class/thermal/thermal_zone0/temp".to_string(),
+                    FanCurve::new(vec![50, 70], vec![20, 100]),
+                    1.0,
+                )],
+                ..Default::default()
+            }
+            .mock()
+        }
+
+        fn monitor() -> Monitor {
+            Monitor::new("test".to_string(), Duration::from_secs(600))
+        }
+
+        #[test]
+        fn test_local_source() {
+            let cli_mock = cli_mock();
+            let mut source = LocalSource::new(&cli_mock, |_: &[SensorCurve]| Ok((vec![60], None)));
+            let status = source.status().unwrap();
+            assert_eq!(status.temperatures[0].celsius, 60);
+            assert_eq!(status.target_duty, 0.6);
+            assert_eq!(status.applied_duty, 0.6);
+            assert_eq!(status.mode, "auto");
+
+            source
+                .send(ControlCommand::SetManualFor(30, Duration::from_secs(600)))
+                .unwrap();
+            let status = source.status().unwrap();
+            assert_eq!(status.applied_duty, 0.3);
+            assert_eq!(status.mode, "manual");
+            assert!(status.manual_remaining_secs.is_some());
+
+            let mut failing = LocalSource::new(&cli_mock, |_: &[SensorCurve]| {
+                Err("sensor unreadable".to_string())
+            });
+            assert_eq!(failing.status(), Err("sensor unreadable".to_string()));
+        }
+
+        #[test]
+        fn test_handle_key() {
+            let mut monitor = monitor();
+            let none = KeyModifiers::NONE;
+            assert_eq!(monitor.handle_key(KeyCode::Char('q'), none), Action::Quit);
+            assert_eq!(
+                monitor.handle_key(KeyCode::Char('c'), KeyModifiers::CONTROL),
+                Action::Quit
+            );
+            assert_eq!(
+                monitor.handle_key(KeyCode::Char('4'), none),
+                Action::Send(ControlCommand::SetManualFor(40, Duration::from_secs(600)))
+            );
+            assert_eq!(
+                monitor.handle_key(KeyCode::Char('0'), none),
+                Action::Send(ControlCommand::SetManualFor(100, Duration::from_secs(600)))
+            );
+            assert_eq!(
+                monitor.handle_key(KeyCode::Char('a'), none),
+                Action::Send(ControlCommand::ClearManual)
+            );
+            assert_eq!(monitor.handle_key(KeyCode::Char('x'), none), Action::None);
+
+            // profiles of the source, cycling from the active one
+            assert_eq!(monitor.handle_key(KeyCode::Char('p'), none), Action::None);
+            let cli_mock = cli_mock();
+            let mut source = LocalSource::new(&cli_mock, |_: &[SensorCurve]| Ok((vec![60], None)));
+            monitor.update(source.status());
+            assert_eq!(
+                monitor.handle_key(KeyCode::Char('p'), none),
+                Action::Send(ControlCommand::SetProfile("silent".to_string()))
+            );
+            source
+                .send(ControlCommand::SetProfile("performance".to_string()))
+                .unwrap();
+            monitor.update(source.status());
+            assert_eq!(
+                monitor.handle_key(KeyCode::Char('p'), none),
+                Action::Send(ControlCommand::SetProfile("silent".to_string()))
+            );
+        }
+
+        #[test]
+        fn test_draw() {
+            let cli_mock = cli_mock();
+            let mut source =
+                LocalSource::new(&cli_mock, |_: &[SensorCurve]| Ok((vec![60], Some(2100))));
+            let mut monitor = monitor();
+            monitor.update(source.status());
+            monitor.update(Err("Daemon unreachable".to_string()));
+
+            let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+            terminal.draw(|frame| monitor.draw(frame)).unwrap();
+            let screen: String = terminal
+                .backend()
+                .buffer()
+                .content()
+                .iter()
+                .map(|cell| cell.symbol())
+                .collect();
+            assert!(screen.contains("rpi-temp-fan-pwm — test"));
+            assert!(screen.contains("Mode: auto"));
+            assert!(screen.contains("RPM: 2100"));
+            assert!(screen.contains("thermal_zone0"));
+            assert!(screen.contains(" 60°C"));
+            assert!(screen.contains("60%"));
+            assert!(screen.contains("Daemon unreachable"));
+            assert_eq!(monitor.duty_history, VecDeque::from([60]));
+        }
+    }
+}
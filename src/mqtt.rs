@@ -0,0 +1,406 @@
+pub mod mqtt_publisher {
+    use crate::cli_arguments::cli_args::MqttArgs;
+    use crate::control::control_command::ControlCommand;
+    use crate::metrics::fan_metrics::Metrics;
+    use log::{debug, error, info, warn};
+    use rumqttc::{Client, Event, LastWill, MqttOptions, Packet, QoS};
+    use serde_json::{json, Map, Value};
+    use std::io::{self};
+    use std::sync::mpsc::Sender;
+    use std::thread;
+    use std::time::Duration;
+
+    const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+    /// Publish fan state to an MQTT broker and forward manual speed commands to the control loop.
+    /// The connection is driven by a background thread, publishing never blocks the caller.
+    pub struct MqttPublisher {
+        client: Client,
+        state_topic: String,
+    }
+
+    // topics shared by the publisher and the connection thread
+    #[derive(Debug, Clone)]
+    struct Topics {
+        state: String,
+        availability: String,
+        command: String,
+    }
+
+    impl MqttPublisher {
+        /// Connect to the broker; `sensors` are the temperature sources exposed to Home Assistant.
+        pub fn start(
+            args: &MqttArgs,
+            sensors: &[String],
+            has_rpm: bool,
+            commands: Sender<ControlCommand>,
+        ) -> Result<Self, io::Error> {
+            let host = args.get_host().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "Missing MQTT broker host")
+            })?;
+            let node_id = args.get_node_id();
+            let base_topic = args.get_base_topic();
+            let topics = Topics {
+                state: format!("{base_topic}/state"),
+                availability: format!("{base_topic}/availability"),
+                command: format!("{base_topic}/manual_speed/set"),
+            };
+
+            let mut options =
+                MqttOptions::new(format!("rpi-temp-fan-pwm-{node_id}"), host, args.get_port());
+            options.set_keep_alive(Duration::from_secs(30));
+            options.set_last_will(LastWill::new(
+                &topics.availability,
+                "offline",
+                QoS::AtLeastOnce,
+                true,
+            ));
+            if let Some((user, password)) = args.get_credentials() {
+                options.set_credentials(user, password);
+            }
+
+            let (client, mut connection) = Client::new(options, 32);
+            let discovery = discovery_messages(
+                args.get_discovery_prefix(),
+                &node_id,
+                &topics,
+                sensors,
+                has_rpm,
+            );
+
+            let thread_client = client.clone();
+            let thread_topics = topics.clone();
+            thread::Builder::new()
+                .name("mqtt".to_string())
+                .spawn(move || {
+                    let mut reconnect_delay = Duration::from_secs(1);
+                    for event in connection.iter() {
+                        match event {
+                            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                                info!("MQTT connected");
+                                reconnect_delay = Duration::from_secs(1);
+                                // a clean session forgets subscriptions, renew them at each connection
+                                on_connect(&thread_client, &thread_topics, &discovery);
+                            }
+                            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                                if publish.topic == thread_topics.command {
+                                    handle_command(&publish.payload, &commands);
+                                }
+                            }
+                            Ok(_) => {}
+                            Err(e) => {
+                                warn!(
+                                    "MQTT connection error, retrying in {:?}: {}",
+                                    reconnect_delay, e
+                                );
+                                thread::sleep(reconnect_delay);
+                                reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                            }
+                        }
+                    }
+                })?;
+
+            info!("MQTT publishing to {} on {}", topics.state, host);
+            Ok(MqttPublisher {
+                client,
+                state_topic: topics.state,
+            })
+        }
+
+        /// Queue the state of the last iteration, dropped if the broker is unreachable.
+        pub fn publish(&self, metrics: &Metrics) {
+            let mut state = Map::new();
+            for (i, (_, temp)) in metrics.get_temperatures().iter().enumerate() {
+                state.insert(format!("temperature_{i}"), json!(temp));
+            }
+            state.insert(
+                "duty".to_string(),
+                json!((metrics.get_applied_duty() * 100.0).round()),
+            );
+            if let Some(rpm) = metrics.get_rpm() {
+                state.insert("rpm".to_string(), json!(rpm));
+            }
+
+            let payload = Value::Object(state).to_string();
+            if let Err(e) =
+                self.client
+                    .try_publish(&self.state_topic, QoS::AtMostOnce, false, payload)
+            {
+                debug!("MQTT state not published: {}", e);
+            }
+        }
+    }
+
+    fn on_connect(client: &Client, topics: &Topics, discovery: &[(String, String)]) {
+        if let Err(e) = client.try_subscribe(&topics.command, QoS::AtLeastOnce) {
+            error!("MQTT subscribe to {} failed: {}", topics.command, e);
+        }
+        for (topic, payload) in discovery {
+            if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, true, payload.as_str()) {
+                error!("MQTT discovery publish to {} failed: {}", topic, e);
+            }
+        }
+        if let Err(e) = client.try_publish(&topics.availability, QoS::AtLeastOnce, true, "online") {
+            error!("MQTT availability publish failed: {}", e);
+        }
+    }
+
+    // `1`-`100` force the speed, `0` or `auto` return to automatic control
+    fn handle_command(payload: &[u8], commands: &Sender<ControlCommand>) {
+        let payload = String::from_utf8_lossy(payload);
+        let command = match payload.trim() {
+            "0" | "auto" | "" => ControlCommand::ClearManual,
+            value => match value.parse::<f64>() {
+                Ok(speed) if (1.0..=100.0).contains(&speed) => {
+                    ControlCommand::SetManual(speed.round() as u8)
+                }
+                _ => {
+                    warn!("Invalid MQTT manual speed `{}`", value);
+                    return;
+                }
+            },
+        };
+        info!("MQTT command: {:?}", command);
+        let _ = commands.send(command);
+    }
+
+    // (topic, payload) of the Home Assistant discovery configs
+    fn discovery_messages(
+        prefix: &str,
+        node_id: &str,
+        topics: &Topics,
+        sensors: &[String],
+        has_rpm: bool,
+    ) -> Vec<(String, String)> {
+        let device = json!({
+            "identifiers": [format!("rpi-temp-fan-pwm-{node_id}")],
+            "name": format!("{node_id} fan"),
+            "model": "rpi-temp-fan-pwm",
+            "sw_version": env!("CARGO_PKG_VERSION"),
+        });
+        let sensor = |object_id: &str, name: &str, template: &str, extra: Value| {
+            let mut config = json!({
+                "name": name,
+                "unique_id": format!("{node_id}_{object_id}"),
+                "state_topic": topics.state,
+                "availability_topic": topics.availability,
+                "value_template": template,
+                "device": device,
+            });
+            if let (Some(config), Value::Object(extra)) = (config.as_object_mut(), extra) {
+                config.extend(extra);
+            }
+            (
+                format!("{prefix}/sensor/{node_id}/{object_id}/config"),
+                config.to_string(),
+            )
+        };
+
+        let mut messages = Vec::new();
+        for (i, path) in sensors.iter().enumerate() {
+            messages.push(sensor(
+                &format!("temperature_{i}"),
+                &format!("Temperature {path}"),
+                &format!("{{{{ value_json.temperature_{i} }}}}"),
+                json!({"device_class": "temperature", "unit_of_measurement": "°C", "state_class": "measurement"}),
+            ));
+        }
+        messages.push(sensor(
+            "duty",
+            "Fan duty",
+            "{{ value_json.duty }}",
+            json!({"unit_of_measurement": "%", "state_class": "measurement"}),
+        ));
+        if has_rpm {
+            messages.push(sensor(
+                "rpm",
+                "Fan speed",
+                "{{ value_json.rpm }}",
+                json!({"unit_of_measurement": "rpm", "state_class": "measurement"}),
+            ));
+        }
+
+        let number = json!({
+            "name": "Fan manual speed",
+            "unique_id": format!("{node_id}_manual_speed"),
+            "command_topic": topics.command,
+            "state_topic": topics.state,
+            "availability_topic": topics.availability,
+            "value_template": "{{ value_json.duty }}",
+            "min": 0,
+            "max": 100,
+            "unit_of_measurement": "%",
+            "device": device,
+        });
+        messages.push((
+            format!("{prefix}/number/{node_id}/manual_speed/config"),
+            number.to_string(),
+        ));
+        messages
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::io::{Read, Write};
+        use std::net::{TcpListener, TcpStream};
+        use std::sync::mpsc;
+
+        // minimal MQTT 3.1.1 broker stand-in, one client, QoS 0/1
+        struct Broker {
+            stream: TcpStream,
+        }
+
+        impl Broker {
+            fn accept(listener: &TcpListener) -> Broker {
+                let (stream, _) = listener.accept().unwrap();
+                stream
+                    .set_read_timeout(Some(Duration::from_secs(10)))
+                    .unwrap();
+                let mut broker = Broker { stream };
+                let (header, _) = broker.read_packet();
+                assert_eq!(header >> 4, 1, "expected CONNECT");
+                broker.stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+                broker
+            }
+
+            fn read_packet(&mut self) -> (u8, Vec<u8>) {
+                let mut byte = [0u8; 1];
+                self.stream.read_exact(&mut byte).unwrap();
+                let header = byte[0];
+                let (mut len, mut shift) = (0usize, 0);
+                loop {
+                    self.stream.read_exact(&mut byte).unwrap();
+                    len |= usize::from(byte[0] & 0x7f) << shift;
+                    shift += 7;
+                    if byte[0] & 0x80 == 0 {
+                        break;
+                    }
+                }
+                let mut body = vec![0u8; len];
+                self.stream.read_exact(&mut body).unwrap();
+                (header, body)
+            }
+
+            // next PUBLISH received, acknowledging packets on the way
+            fn next_publish(&mut self) -> (String, String) {
+                loop {
+                    let (header, body) = self.read_packet();
+                    match header >> 4 {
+                        3 => {
+                            let topic_len = usize::from(u16::from_be_bytes([body[0], body[1]]));
+                            let topic = String::from_utf8(body[2..2 + topic_len].to_vec()).unwrap();
+                            let mut offset = 2 + topic_len;
+                            if (header >> 1) & 0x03 > 0 {
+                                self.stream
+                                    .write_all(&[0x40, 0x02, body[offset], body[offset + 1]])
+                                    .unwrap();
+                                offset += 2;
+                            }
+                            let payload = String::from_utf8(body[offset..].to_vec()).unwrap();
+                            return (topic, payload);
+                        }
+                        8 => {
+                            self.stream
+                                .write_all(&[0x90, 0x03, body[0], body[1], 0x00])
+                                .unwrap();
+                        }
+                        12 => self.stream.write_all(&[0xd0, 0x00]).unwrap(),
+                        _ => {}
+                    }
+                }
+            }
+
+            fn publish(&mut self, topic: &str, payload: &str) {
+                let mut packet = vec![0x30, (2 + topic.len() + payload.len()) as u8];
+                packet.extend_from_slice(&(topic.len() as u16).to_be_bytes());
+                packet.extend_from_slice(topic.as_bytes());
+                packet.extend_from_slice(payload.as_bytes());
+                self.stream.write_all(&packet).unwrap();
+            }
+        }
+
+        #[test]
+        fn test_discovery_state_and_command() {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let args = MqttArgs::new(
+                Some("127.0.0.1".to_string()),
+                port,
+                Some("testpi".to_string()),
+                None,
+            );
+            let (tx, rx) = mpsc::channel();
+
+            let publisher = MqttPublisher::start(
+                &args,
+                &["/sys/class/thermal/thermal_zone0/temp".to_string()],
+                false,
+                tx,
+            )
+            .unwrap();
+            let mut broker = Broker::accept(&listener);
+
+            // discovery configs then availability
+            let mut topics = Vec::new();
+            loop {
+                let (topic, payload) = broker.next_publish();
+                if topic == "rpi-temp-fan-pwm/testpi/availability" {
+                    assert_eq!(payload, "online");
+                    break;
+                }
+                let config: Value = serde_json::from_str(&payload).unwrap();
+                assert_eq!(
+                    config["availability_topic"],
+                    "rpi-temp-fan-pwm/testpi/availability"
+                );
+                topics.push(topic);
+            }
+            assert_eq!(
+                topics,
+                vec![
+                    "homeassistant/sensor/testpi/temperature_0/config",
+                    "homeassistant/sensor/testpi/duty/config",
+                    "homeassistant/number/testpi/manual_speed/config",
+                ]
+            );
+
+            // state of an iteration
+            let mut metrics = Metrics::default();
+            metrics.set_temperatures(vec![("cpu".to_string(), 61)]);
+            metrics.set_applied_duty(0.42);
+            publisher.publish(&metrics);
+            let (topic, payload) = broker.next_publish();
+            assert_eq!(topic, "rpi-temp-fan-pwm/testpi/state");
+            let state: Value = serde_json::from_str(&payload).unwrap();
+            assert_eq!(state["temperature_0"], 61);
+            assert_eq!(state["duty"], 42.0);
+
+            // manual override from Home Assistant
+            broker.publish("rpi-temp-fan-pwm/testpi/manual_speed/set", "55");
+            assert_eq!(
+                rx.recv_timeout(Duration::from_secs(10)),
+                Ok(ControlCommand::SetManual(55))
+            );
+            broker.publish("rpi-temp-fan-pwm/testpi/manual_speed/set", "auto");
+            assert_eq!(
+                rx.recv_timeout(Duration::from_secs(10)),
+                Ok(ControlCommand::ClearManual)
+            );
+        }
+
+        #[test]
+        fn test_handle_command() {
+            let (tx, rx) = mpsc::channel();
+            handle_command(b"100", &tx);
+            handle_command(b"0", &tx);
+            handle_command(b"150", &tx);
+            handle_command(b"fast", &tx);
+            drop(tx);
+            assert_eq!(
+                rx.iter().collect::<Vec<_>>(),
+                vec![ControlCommand::SetManual(100), ControlCommand::ClearManual]
+            );
+        }
+    }
+}
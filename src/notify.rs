@@ -0,0 +1,217 @@
+pub mod sd_notify {
+    use log::{debug, warn};
+    use std::env;
+    use std::io::{self};
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+    use std::time::{Duration, Instant};
+
+    /// Client of the systemd notify socket, see sd_notify(3).
+    /// Without NOTIFY_SOCKET, e.g. when not started by systemd, every message is dropped.
+    #[derive(Debug)]
+    pub struct Notifier {
+        target: Option<(UnixDatagram, SocketAddr)>,
+        watchdog: Option<Duration>,
+        last_ping: Option<Instant>,
+    }
+
+    impl Notifier {
+        /// Notifier configured from NOTIFY_SOCKET, WATCHDOG_USEC and WATCHDOG_PID.
+        pub fn from_env() -> Self {
+            let target = env::var_os("NOTIFY_SOCKET").and_then(|path| {
+                match socket_addr(&path.to_string_lossy()).and_then(|addr| {
+                    let socket = UnixDatagram::unbound()?;
+                    Ok((socket, addr))
+                }) {
+                    Ok(target) => Some(target),
+                    Err(e) => {
+                        warn!("Invalid NOTIFY_SOCKET {}: {}", path.to_string_lossy(), e);
+                        None
+                    }
+                }
+            });
+            let watchdog = watchdog_period(
+                env::var("WATCHDOG_USEC").ok().as_deref(),
+                env::var("WATCHDOG_PID").ok().as_deref(),
+                std::process::id(),
+            );
+            Notifier::new(target, watchdog)
+        }
+
+        fn new(target: Option<(UnixDatagram, SocketAddr)>, watchdog: Option<Duration>) -> Self {
+            Notifier {
+                target,
+                watchdog,
+                last_ping: None,
+            }
+        }
+
+        /// Watchdog timeout requested by the service manager.
+        pub fn get_watchdog(&self) -> Option<Duration> {
+            self.watchdog
+        }
+
+        /// Send newline separated `KEY=VALUE` assignments.
+        pub fn notify(&self, state: &str) -> Result<(), io::Error> {
+            match self.target.as_ref() {
+                Some((socket, addr)) => {
+                    socket.send_to_addr(state.as_bytes(), addr)?;
+                    Ok(())
+                }
+                None => Ok(()),
+            }
+        }
+
+        pub fn ready(&self) {
+            self.send("READY=1");
+        }
+
+        pub fn stopping(&self) {
+            self.send("STOPPING=1");
+        }
+
+        /// Free-form status shown by `systemctl status`.
+        pub fn status(&self, status: &str) {
+            self.send(&format!("STATUS={status}"));
+        }
+
+        /// Keep-alive ping, sent at most every quarter of the watchdog timeout so that an update
+        /// period just under half of the timeout still pings well in time.
+        pub fn watchdog(&mut self, now: Instant) {
+            let Some(timeout) = self.watchdog else {
+                return;
+            };
+            if self
+                .last_ping
+                .is_some_and(|ping| now.duration_since(ping) < timeout / 4)
+            {
+                return;
+            }
+            self.send("WATCHDOG=1");
+            self.last_ping = Some(now);
+        }
+
+        // a lost notification isn't worth stopping the fan control
+        fn send(&self, state: &str) {
+            if let Err(e) = self.notify(state) {
+                debug!("Error sending {} to the notify socket: {}", state, e);
+            }
+        }
+    }
+
+    /// Socket path, or Linux abstract socket when starting with `@`.
+    fn socket_addr(path: &str) -> Result<SocketAddr, io::Error> {
+        match path.strip_prefix('@') {
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                SocketAddr::from_abstract_name(name)
+            }
+            None if path.starts_with('/') => SocketAddr::from_pathname(path),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not an absolute path or abstract socket",
+            )),
+        }
+    }
+
+    /// Watchdog timeout, only when WATCHDOG_PID is unset or our own pid.
+    fn watchdog_period(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+        if pid.is_some_and(|pid| pid.parse() != Ok(own_pid)) {
+            return None;
+        }
+        usec?
+            .parse()
+            .ok()
+            .filter(|usec| *usec > 0)
+            .map(Duration::from_micros)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn listener(name: &str) -> (UnixDatagram, std::path::PathBuf) {
+            let path = std::env::temp_dir().join(format!(
+                "rpi-temp-fan-pwm-notify-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_file(&path);
+            let socket = UnixDatagram::bind(&path).unwrap();
+            socket
+                .set_read_timeout(Some(Duration::from_secs(1)))
+                .unwrap();
+            (socket, path)
+        }
+
+        fn notifier(path: &std::path::Path, watchdog: Option<Duration>) -> Notifier {
+            let addr = socket_addr(path.to_str().unwrap()).unwrap();
+            Notifier::new(Some((UnixDatagram::unbound().unwrap(), addr)), watchdog)
+        }
+
+        fn recv(socket: &UnixDatagram) -> String {
+            let mut buf = [0; 256];
+            let len = socket.recv(&mut buf).unwrap();
+            String::from_utf8_lossy(&buf[..len]).to_string()
+        }
+
+        #[test]
+        fn test_messages() {
+            let (socket, path) = listener("messages");
+            let notifier = notifier(&path, None);
+
+            notifier.ready();
+            assert_eq!(recv(&socket), "READY=1");
+            notifier.status("52°C, duty 40%");
+            assert_eq!(recv(&socket), "STATUS=52°C, duty 40%");
+            notifier.stopping();
+            assert_eq!(recv(&socket), "STOPPING=1");
+            let _ = std::fs::remove_file(path);
+        }
+
+        #[test]
+        fn test_watchdog() {
+            let (socket, path) = listener("watchdog");
+            let mut notifier = notifier(&path, Some(Duration::from_secs(10)));
+            let start = Instant::now();
+
+            notifier.watchdog(start);
+            notifier.watchdog(start + Duration::from_secs(2));
+            notifier.watchdog(start + Duration::from_secs(3));
+            assert_eq!(recv(&socket), "WATCHDOG=1");
+            assert_eq!(recv(&socket), "WATCHDOG=1");
+            socket.set_nonblocking(true).unwrap();
+            assert!(socket.recv(&mut [0; 16]).is_err());
+            let _ = std::fs::remove_file(path);
+        }
+
+        #[test]
+        fn test_without_socket() {
+            let mut notifier = Notifier::new(None, Some(Duration::from_secs(10)));
+            assert!(notifier.notify("READY=1").is_ok());
+            notifier.watchdog(Instant::now());
+        }
+
+        #[test]
+        fn test_watchdog_period() {
+            assert_eq!(
+                watchdog_period(Some("20000000"), None, 42),
+                Some(Duration::from_secs(20))
+            );
+            assert_eq!(
+                watchdog_period(Some("20000000"), Some("42"), 42),
+                Some(Duration::from_secs(20))
+            );
+            // watchdog meant for another process
+            assert_eq!(watchdog_period(Some("20000000"), Some("7"), 42), None);
+            assert_eq!(watchdog_period(Some("0"), None, 42), None);
+            assert_eq!(watchdog_period(None, None, 42), None);
+        }
+
+        #[test]
+        fn test_socket_addr() {
+            assert!(socket_addr("/run/systemd/notify").is_ok());
+            assert!(socket_addr("@systemd-notify").is_ok());
+            assert!(socket_addr("relative/notify").is_err());
+        }
+    }
+}
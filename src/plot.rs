@@ -0,0 +1,163 @@
+pub mod curve_plot {
+    use crate::controller::fan_controller::{FanController, FanMode};
+    use crate::curve::fan_curve::FanCurve;
+    use std::fmt::Write;
+    use std::ops::RangeInclusive;
+
+    /// Rows of the terminal chart, 5% each.
+    const CHART_HEIGHT: usize = 20;
+
+    /// Widest chart, longer ranges are sampled.
+    const CHART_WIDTH: usize = 60;
+
+    /// Degrees shown around the curve steps by default.
+    const RANGE_MARGIN: u8 = 10;
+
+    /// Warnings about steps allowed but usually unintended, the temperature steps are already
+    /// checked to increase when parsing the curve.
+    pub fn check(curve: &FanCurve) -> Vec<String> {
+        let temps = curve.get_temp_step();
+        let speeds = curve.get_speed_step();
+        (1..temps.len())
+            .filter(|&i| speeds[i] < speeds[i - 1])
+            .map(|i| {
+                format!(
+                    "Speed decreases from {}% to {}% between {}°C and {}°C",
+                    speeds[i - 1],
+                    speeds[i],
+                    temps[i - 1],
+                    temps[i]
+                )
+            })
+            .collect()
+    }
+
+    /// Temperatures from `RANGE_MARGIN` below the first step to `RANGE_MARGIN` above the last one.
+    pub fn default_range(curves: &[&FanCurve]) -> RangeInclusive<u8> {
+        let first = curves
+            .iter()
+            .filter_map(|c| c.get_temp_step().first())
+            .min()
+            .copied()
+            .unwrap_or(0);
+        let last = curves
+            .iter()
+            .filter_map(|c| c.get_temp_step().last())
+            .max()
+            .copied()
+            .unwrap_or(100);
+        first.saturating_sub(RANGE_MARGIN)..=last.saturating_add(RANGE_MARGIN)
+    }
+
+    /// Duty cycle at each degree, every sensor reading the same temperature.
+    pub fn duty_points(
+        controller: &FanController,
+        range: RangeInclusive<u8>,
+    ) -> Vec<(u8, f64, FanMode)> {
+        let sensors = controller.get_curves().len();
+        range
+            .map(|temp| {
+                let (duty, mode) = controller.target(&vec![temp; sensors], None);
+                (temp, duty, mode)
+            })
+            .collect()
+    }
+
+    /// Chart of the duty cycle, a `*` per column at the closest 5% row.
+    pub fn render_chart(points: &[(u8, f64, FanMode)]) -> String {
+        let mut out = String::new();
+        if points.is_empty() {
+            return out;
+        }
+        let columns = points.len().min(CHART_WIDTH);
+        // column -> point, sampling long ranges evenly
+        let sample = |column: usize| &points[column * (points.len() - 1) / (columns - 1).max(1)];
+        let rows: Vec<usize> = (0..columns)
+            .map(|c| (sample(c).1 * CHART_HEIGHT as f64).round() as usize)
+            .collect();
+
+        for row in (0..=CHART_HEIGHT).rev() {
+            let label = if row % 5 == 0 {
+                format!("{:>3}%", row * 100 / CHART_HEIGHT)
+            } else {
+                "    ".to_string()
+            };
+            let line: String = rows
+                .iter()
+                .map(|&r| if r == row { '*' } else { ' ' })
+                .collect();
+            let _ = writeln!(out, "{label} |{}", line.trim_end());
+        }
+        let _ = writeln!(out, "     +{}", "-".repeat(columns));
+
+        let first = format!("{}°C", sample(0).0);
+        let last = format!("{}°C", sample(columns - 1).0);
+        let gap = (columns + 1).saturating_sub(first.chars().count() + last.chars().count());
+        let _ = writeln!(out, "      {first}{}{last}", " ".repeat(gap));
+        out
+    }
+
+    /// Temperature to duty cycle table, at 1°C resolution.
+    pub fn render_table(points: &[(u8, f64, FanMode)]) -> String {
+        let mut out = String::from("Temp   Duty  Mode\n");
+        for (temp, duty, mode) in points {
+            let _ = writeln!(out, "{:>3}°C  {:>3.0}%  {}", temp, duty * 100.0, mode);
+        }
+        out
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_check() {
+            let curve = FanCurve::new(vec![50, 70, 80], vec![20, 50, 100]);
+            assert!(check(&curve).is_empty());
+
+            let curve = FanCurve::new(vec![50, 70, 80], vec![20, 60, 40]);
+            assert_eq!(
+                check(&curve),
+                vec!["Speed decreases from 60% to 40% between 70°C and 80°C"]
+            );
+        }
+
+        #[test]
+        fn test_default_range() {
+            let cpu = FanCurve::new(vec![50, 70, 80], vec![20, 50, 100]);
+            let nvme = FanCurve::new(vec![5, 60], vec![0, 60]);
+            assert_eq!(default_range(&[&cpu]), 40..=90);
+            assert_eq!(default_range(&[&cpu, &nvme]), 0..=90);
+        }
+
+        #[test]
+        fn test_render() {
+            let points: Vec<(u8, f64, FanMode)> = (40..=50)
+                .map(|t| (t, f64::from(t - 40) / 10.0, FanMode::Auto))
+                .collect();
+
+            let chart = render_chart(&points);
+            let lines: Vec<&str> = chart.lines().collect();
+            assert_eq!(lines.len(), CHART_HEIGHT + 3);
+            assert_eq!(lines[0], "100% |          *");
+            assert_eq!(lines[CHART_HEIGHT], "  0% |*");
+            assert_eq!(lines[CHART_HEIGHT + 1], "     +-----------");
+            assert_eq!(lines[CHART_HEIGHT + 2], "      40°C    50°C");
+
+            let table = render_table(&points[..2]);
+            assert_eq!(
+                table,
+                "Temp   Duty  Mode\n 40°C    0%  auto\n 41°C   10%  auto\n"
+            );
+        }
+
+        #[test]
+        fn test_render_sampled() {
+            let points: Vec<(u8, f64, FanMode)> =
+                (0..=120).map(|t| (t, 0.5, FanMode::Manual)).collect();
+            let chart = render_chart(&points);
+            assert!(chart.contains(&format!(" 50% |{}\n", "*".repeat(CHART_WIDTH))));
+            assert!(chart.ends_with("120°C\n"));
+        }
+    }
+}
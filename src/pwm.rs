@@ -1,13 +1,12 @@
-use crate::cli_arguments::cli_args::CliArgsTrait;
-use log::{debug, error, info};
+use log::error;
 use num_traits::cast::ToPrimitive;
 use std::io::{self};
 
 pub mod pwm_manager {
 
-    use crate::cli_arguments::cli_args::CliArgsTrait;
-    use log::{debug, error, info};
+    use log::{error, info};
     use rppal::pwm::{Channel, Polarity, Pwm};
+    use std::cell::Cell;
     use std::io::{self};
 
     use mockall::predicate::*;
@@ -23,17 +22,14 @@ pub mod pwm_manager {
         where
             Self: std::marker::Sized;
 
-        fn set_pwm<T: CliArgsTrait + 'static>(
-            &self,
-            temp: &str,
-            cli_args: &T,
-        ) -> Result<(), Box<dyn std::error::Error>>;
-
         fn set_frequency(
             &self,
             freq: f64,
             fan_speed: f64,
         ) -> Result<(), Box<dyn std::error::Error>>;
+
+        /// Whether the PWM channel is disabled when the manager is dropped.
+        fn set_reset_on_drop(&mut self, reset_on_drop: bool);
     }
 
     #[derive(Debug)]
@@ -75,35 +71,44 @@ pub mod pwm_manager {
             Ok(())
         }
 
-        fn set_pwm<T: CliArgsTrait + 'static>(
-            &self,
-            temp: &str,
-            cli_args: &T,
-        ) -> Result<(), Box<dyn std::error::Error>> {
-            // Convert the string to a u8
-            let temp: u8 = super::parse_temp_string(temp)?;
-            debug!("Temperature: {}", temp);
+        fn set_reset_on_drop(&mut self, reset_on_drop: bool) {
+            self.pwm.set_reset_on_drop(reset_on_drop);
+        }
+    }
 
-            let fan_speed = super::get_fan_speed_linear(temp, cli_args);
-            let pwm_freq = cli_args.get_pwm_freq();
+    /// Backend without hardware, keeping the last duty cycle for replays and simulations.
+    #[derive(Debug, Default)]
+    pub struct NullPwmManager {
+        duty: Cell<f64>,
+    }
 
-            match self.set_frequency(pwm_freq, fan_speed) {
-                Ok(_) => {
-                    debug!("PWM frequency set to {pwm_freq} Hz");
-                    debug!("Fan speed set to {fan_speed}%");
-                }
-                Err(e) => {
-                    error!("Failed to set PWM frequency: {}", e);
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        "Failed to set PWM frequency",
-                    )
-                    .into());
-                }
-            }
+    impl NullPwmManager {
+        pub fn get_duty(&self) -> f64 {
+            self.duty.get()
+        }
+    }
+
+    impl PwmManagerTrait for NullPwmManager {
+        fn build(
+            _pwm_channel: u8,
+            _pwm_freq: f64,
+            pwm_duty: f64,
+        ) -> Result<Self, Box<dyn std::error::Error>> {
+            Ok(Self {
+                duty: Cell::new(pwm_duty),
+            })
+        }
 
+        fn set_frequency(
+            &self,
+            _freq: f64,
+            fan_speed: f64,
+        ) -> Result<(), Box<dyn std::error::Error>> {
+            self.duty.set(fan_speed);
             Ok(())
         }
+
+        fn set_reset_on_drop(&mut self, _reset_on_drop: bool) {}
     }
 
     #[cfg(test)]
@@ -236,7 +241,7 @@ pub mod pwm_manager {
 }
 
 // parse temperature string from file
-fn parse_temp_string(temp: &str) -> Result<u8, Box<dyn std::error::Error>> {
+pub fn parse_temp_string(temp: &str) -> Result<u8, Box<dyn std::error::Error>> {
     // Convert the string to a u8
     match temp.parse::<f32>() {
         Ok(f) => {
@@ -263,169 +268,9 @@ fn parse_temp_string(temp: &str) -> Result<u8, Box<dyn std::error::Error>> {
     }
 }
 
-// Get speed interpolating array's values
-fn get_fan_speed_linear(temp: u8, cli_args: &impl CliArgsTrait) -> f64 {
-    // manually forced value
-    if cli_args.get_manual_speed().is_some() {
-        let val = cli_args.get_manual_speed().unwrap();
-        debug!("manual speed: {}", val);
-        return (val as f64) / 100.0;
-    }
-
-    let cfg_speed = cli_args.get_speed_step();
-    let cfg_temp = cli_args.get_temp_step();
-
-    let mut speed: u8 = *cfg_speed.last().unwrap();
-    let last_temp = *cfg_temp.last().unwrap();
-
-    info!("temp: {}", temp);
-
-    // temp below first value
-    if temp < cfg_temp[0] {
-        debug!("min speed: {}", cfg_speed[0]);
-        speed = cfg_speed[0];
-    } else if temp > last_temp {
-        debug!("max speed: {}", speed);
-        // max value already selected
-    } else {
-        for (i, &step_temp) in cfg_temp.iter().enumerate() {
-            let next_step_temp = cfg_temp[i + 1];
-
-            let speed_step = cfg_speed[i];
-            let next_speed_step = cfg_speed[i + 1];
-
-            debug!("Temperature step[{}]: {}", i, step_temp);
-            debug!("Temperature next step[{}]: {}", i + 1, next_step_temp);
-
-            if (temp >= step_temp) && (temp <= next_step_temp) {
-                // Linear interpolation
-                let temp_range: u8 = next_step_temp - step_temp;
-                let speed_range: u8 = next_speed_step - speed_step;
-                let temp_diff: u8 = temp - step_temp;
-
-                debug!("temp_diff: {}", temp_diff);
-                debug!("temp_range: {}", temp_range);
-                debug!("speed_range: {}", speed_range);
-
-                let speed_range: u16 = speed_range as u16;
-                let temp_diff: u16 = temp_diff as u16;
-                let temp_range: u16 = temp_range as u16;
-                let speed_step: u16 = speed_step as u16;
-                let calc: u16 = speed_range * temp_diff / temp_range;
-
-                speed = (speed_step + calc).try_into().unwrap();
-                debug!("Linear interpolation: {}", speed);
-                break;
-            }
-        }
-    }
-
-    debug!("temp: {}", temp);
-    debug!("speed: {}", speed);
-    (speed as f64) / 100.0
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::cli_arguments::cli_args::MockCliArgsTrait;
-
-    // --- get_fan_speed_linear tests ---
-
-    #[test]
-    fn test_manual_speed() {
-        let mut cli_mock = MockCliArgsTrait::new();
-        cli_mock.expect_get_manual_speed().returning(|| Some(42));
-        cli_mock
-            .expect_get_speed_step()
-            .returning(|| vec![20, 50, 100]);
-        cli_mock
-            .expect_get_temp_step()
-            .returning(|| vec![50, 70, 80]);
-
-        assert_eq!(get_fan_speed_linear(60, &cli_mock), 0.42);
-        assert_eq!(get_fan_speed_linear(80, &cli_mock), 0.42);
-    }
-
-    #[test]
-    fn test_below_first_temp() {
-        let mut cli_mock = MockCliArgsTrait::new();
-        cli_mock.expect_get_manual_speed().returning(|| None);
-        cli_mock
-            .expect_get_speed_step()
-            .returning(|| vec![20, 50, 100]);
-        cli_mock
-            .expect_get_temp_step()
-            .returning(|| vec![50, 70, 80]);
-
-        assert_eq!(get_fan_speed_linear(40, &cli_mock), 0.20);
-        assert_eq!(get_fan_speed_linear(0, &cli_mock), 0.20);
-    }
-
-    #[test]
-    fn test_above_last_temp() {
-        let mut cli_mock = MockCliArgsTrait::new();
-        cli_mock.expect_get_manual_speed().returning(|| None);
-        cli_mock
-            .expect_get_speed_step()
-            .returning(|| vec![20, 50, 100]);
-        cli_mock
-            .expect_get_temp_step()
-            .returning(|| vec![50, 70, 80]);
-
-        assert_eq!(get_fan_speed_linear(90, &cli_mock), 1.0);
-        assert_eq!(get_fan_speed_linear(255, &cli_mock), 1.0);
-    }
-
-    #[test]
-    fn test_exact_temp_steps() {
-        let mut cli_mock = MockCliArgsTrait::new();
-        cli_mock.expect_get_manual_speed().returning(|| None);
-        cli_mock
-            .expect_get_speed_step()
-            .returning(|| vec![20, 50, 100]);
-        cli_mock
-            .expect_get_temp_step()
-            .returning(|| vec![50, 70, 80]);
-
-        assert_eq!(get_fan_speed_linear(50, &cli_mock), 0.2);
-        assert_eq!(get_fan_speed_linear(70, &cli_mock), 0.5);
-        assert_eq!(get_fan_speed_linear(80, &cli_mock), 1.0);
-    }
-
-    #[test]
-    fn test_linear_interpolation() {
-        let mut cli_mock = MockCliArgsTrait::new();
-        cli_mock.expect_get_manual_speed().returning(|| None);
-        cli_mock
-            .expect_get_speed_step()
-            .returning(|| vec![20, 50, 100]);
-        cli_mock
-            .expect_get_temp_step()
-            .returning(|| vec![50, 70, 80]);
-
-        // Between 50 and 70: 20 -> 50
-        assert_eq!(get_fan_speed_linear(65, &cli_mock), 0.42);
-        // Between 70 and 80: 50 -> 100
-        assert_eq!(get_fan_speed_linear(75, &cli_mock), 0.75);
-    }
-
-    #[test]
-    fn test_non_uniform_steps() {
-        let mut cli_mock = MockCliArgsTrait::new();
-        cli_mock.expect_get_manual_speed().returning(|| None);
-        cli_mock
-            .expect_get_speed_step()
-            .returning(|| vec![10, 60, 80]);
-        cli_mock
-            .expect_get_temp_step()
-            .returning(|| vec![40, 60, 90]);
-
-        // Between 40 and 60: 10 -> 60
-        assert_eq!(get_fan_speed_linear(50, &cli_mock), 0.35);
-        // Between 60 and 90: 60 -> 80
-        assert_eq!(get_fan_speed_linear(75, &cli_mock), 0.7);
-    }
 
     // --- parse_temp_string tests ---
 
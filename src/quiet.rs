@@ -0,0 +1,244 @@
+pub mod quiet_schedule {
+    use chrono::{DateTime, FixedOffset, Local, NaiveTime};
+    use log::info;
+    use std::fmt;
+    use std::str::FromStr;
+
+    /// Source of local wall clock time, injectable to test schedules across midnight and DST.
+    pub trait LocalClock {
+        fn now(&self) -> DateTime<FixedOffset>;
+    }
+
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct SystemLocalClock;
+
+    impl LocalClock for SystemLocalClock {
+        fn now(&self) -> DateTime<FixedOffset> {
+            Local::now().fixed_offset()
+        }
+    }
+
+    /// Time window capping the fan speed, with the curves shifted by `temp_offset` degrees.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct QuietProfile {
+        start: NaiveTime,
+        end: NaiveTime,
+        max_speed: u8,
+        temp_offset: u8,
+    }
+
+    impl QuietProfile {
+        pub fn new(start: NaiveTime, end: NaiveTime, max_speed: u8, temp_offset: u8) -> Self {
+            QuietProfile {
+                start,
+                end,
+                max_speed,
+                temp_offset,
+            }
+        }
+
+        /// Whether the wall clock `time` is in the window, which can span midnight.
+        pub fn is_active(&self, time: NaiveTime) -> bool {
+            if self.start <= self.end {
+                self.start <= time && time < self.end
+            } else {
+                time >= self.start || time < self.end
+            }
+        }
+
+        /// Temperature seen by the curves while the profile is active.
+        pub fn shift(&self, temp: u8) -> u8 {
+            temp.saturating_sub(self.temp_offset)
+        }
+
+        /// Cap the duty cycle, in 0.0..=1.0, to the profile maximum speed.
+        pub fn cap(&self, duty: f64) -> f64 {
+            duty.min(f64::from(self.max_speed) / 100.0)
+        }
+    }
+
+    impl fmt::Display for QuietProfile {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "{}-{},{},{}",
+                self.start.format("%H:%M"),
+                self.end.format("%H:%M"),
+                self.max_speed,
+                self.temp_offset
+            )
+        }
+    }
+
+    // Format: START-END,MAX_SPEED[,TEMP_OFFSET], e.g. 22:00-07:00,40,5
+    impl FromStr for QuietProfile {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let fields: Vec<&str> = s.split(',').map(str::trim).collect();
+            if !(2..=3).contains(&fields.len()) {
+                return Err(format!(
+                    "`{s}` isn't in the START-END,MAX_SPEED[,TEMP_OFFSET] format"
+                ));
+            }
+
+            let (start, end) = fields[0]
+                .split_once('-')
+                .ok_or_else(|| format!("`{}` isn't a START-END time window", fields[0]))?;
+            let parse_time = |t: &str| {
+                NaiveTime::parse_from_str(t, "%H:%M")
+                    .map_err(|_| format!("`{t}` isn't a HH:MM time"))
+            };
+
+            let max_speed = fields[1]
+                .parse::<u8>()
+                .ok()
+                .filter(|v| *v <= 100)
+                .ok_or_else(|| format!("`{}` isn't a percentage number", fields[1]))?;
+            let temp_offset = match fields.get(2) {
+                Some(o) => o
+                    .parse::<u8>()
+                    .map_err(|_| format!("`{o}` isn't a valid temperature offset"))?,
+                None => 0,
+            };
+
+            Ok(QuietProfile::new(
+                parse_time(start)?,
+                parse_time(end)?,
+                max_speed,
+                temp_offset,
+            ))
+        }
+    }
+
+    /// Selects the quiet profile of the current local time, first match in configuration order.
+    pub struct QuietSchedule<C: LocalClock> {
+        clock: C,
+        profiles: Vec<QuietProfile>,
+        active: Option<usize>,
+    }
+
+    impl<C: LocalClock> QuietSchedule<C> {
+        pub fn new(clock: C, profiles: Vec<QuietProfile>) -> Self {
+            QuietSchedule {
+                clock,
+                profiles,
+                active: None,
+            }
+        }
+
+        /// Profile active now, logging when it changes.
+        pub fn update(&mut self) -> Option<&QuietProfile> {
+            let time = self.clock.now().time();
+            let active = self.profiles.iter().position(|p| p.is_active(time));
+            if active != self.active {
+                match active {
+                    Some(i) => info!("Quiet profile {} active", self.profiles[i]),
+                    None => info!("Quiet profile ended"),
+                }
+                self.active = active;
+            }
+            active.map(|i| &self.profiles[i])
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::cell::Cell;
+
+        struct FakeClock {
+            now: Cell<DateTime<FixedOffset>>,
+        }
+
+        impl FakeClock {
+            fn at(time: &str) -> Self {
+                FakeClock {
+                    now: Cell::new(DateTime::parse_from_rfc3339(time).unwrap()),
+                }
+            }
+        }
+
+        impl LocalClock for &FakeClock {
+            fn now(&self) -> DateTime<FixedOffset> {
+                self.now.get()
+            }
+        }
+
+        fn set(clock: &FakeClock, time: &str) {
+            clock.now.set(DateTime::parse_from_rfc3339(time).unwrap());
+        }
+
+        #[test]
+        fn test_parse() {
+            let profile: QuietProfile = "22:00-07:00,40,5".parse().unwrap();
+            assert_eq!(profile.to_string(), "22:00-07:00,40,5");
+            assert_eq!(
+                "22:00-07:00,40".parse::<QuietProfile>().unwrap().shift(60),
+                60
+            );
+            assert!("22:00,40".parse::<QuietProfile>().is_err());
+            assert!("22:00-25:00,40".parse::<QuietProfile>().is_err());
+            assert!("22:00-07:00,140".parse::<QuietProfile>().is_err());
+            assert!("22:00-07:00".parse::<QuietProfile>().is_err());
+        }
+
+        #[test]
+        fn test_cap_and_shift() {
+            let profile: QuietProfile = "22:00-07:00,40,5".parse().unwrap();
+            assert_eq!(profile.cap(0.9), 0.4);
+            assert_eq!(profile.cap(0.3), 0.3);
+            assert_eq!(profile.shift(60), 55);
+            assert_eq!(profile.shift(3), 0);
+        }
+
+        #[test]
+        fn test_across_midnight() {
+            let clock = FakeClock::at("2026-06-01T21:59:00+02:00");
+            let mut schedule = QuietSchedule::new(&clock, vec!["22:00-07:00,40".parse().unwrap()]);
+            assert!(schedule.update().is_none());
+
+            set(&clock, "2026-06-01T22:00:00+02:00");
+            assert!(schedule.update().is_some());
+            set(&clock, "2026-06-02T00:30:00+02:00");
+            assert!(schedule.update().is_some());
+            set(&clock, "2026-06-02T06:59:59+02:00");
+            assert!(schedule.update().is_some());
+            set(&clock, "2026-06-02T07:00:00+02:00");
+            assert!(schedule.update().is_none());
+        }
+
+        #[test]
+        fn test_across_dst() {
+            // the window follows the wall clock, not the elapsed time
+            let clock = FakeClock::at("2026-03-29T01:30:00+01:00");
+            let mut schedule = QuietSchedule::new(&clock, vec!["01:00-03:30,30".parse().unwrap()]);
+            assert!(schedule.update().is_some());
+            // spring forward: one hour later it's 03:30 CEST
+            set(&clock, "2026-03-29T03:30:00+02:00");
+            assert!(schedule.update().is_none());
+
+            let mut schedule = QuietSchedule::new(&clock, vec!["02:00-02:45,30".parse().unwrap()]);
+            // fall back: 02:30 happens twice, in both the window is active
+            set(&clock, "2026-10-25T02:30:00+02:00");
+            assert!(schedule.update().is_some());
+            set(&clock, "2026-10-25T02:50:00+02:00");
+            assert!(schedule.update().is_none());
+            set(&clock, "2026-10-25T02:30:00+01:00");
+            assert!(schedule.update().is_some());
+        }
+
+        #[test]
+        fn test_first_profile_wins() {
+            let clock = FakeClock::at("2026-06-01T23:00:00+00:00");
+            let mut schedule = QuietSchedule::new(
+                &clock,
+                vec![
+                    "22:00-07:00,40".parse().unwrap(),
+                    "23:00-05:00,20".parse().unwrap(),
+                ],
+            );
+            assert_eq!(schedule.update().unwrap().cap(1.0), 0.4);
+        }
+    }
+}
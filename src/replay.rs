@@ -0,0 +1,145 @@
+pub mod history_replay {
+    use crate::cli_arguments::cli_args::CliArgsTrait;
+    use crate::controller::fan_controller::{ControlInput, FanController};
+    use crate::history::history_recorder::HistoryRow;
+    use crate::pwm::pwm_manager::NullPwmManager;
+    use crate::quiet::quiet_schedule::{LocalClock, QuietSchedule};
+    use chrono::{DateTime, FixedOffset};
+    use std::cell::Cell;
+    use std::time::Instant;
+
+    /// Wall clock following the recorded timestamps, so quiet profiles apply as they would have.
+    struct ReplayClock {
+        now: Cell<DateTime<FixedOffset>>,
+    }
+
+    impl LocalClock for &ReplayClock {
+        fn now(&self) -> DateTime<FixedOffset> {
+            self.now.get()
+        }
+    }
+
+    /// Columns of the recorded `sensors` feeding each curve, in curve order.
+    pub fn sensor_columns(
+        cli_args: &impl CliArgsTrait,
+        sensors: &[String],
+    ) -> Result<Vec<usize>, String> {
+        cli_args
+            .get_curves()
+            .iter()
+            .map(|sc| {
+                sensors
+                    .iter()
+                    .position(|s| s == sc.get_sensor())
+                    .ok_or_else(|| format!("Sensor {} isn't in the history", sc.get_sensor()))
+            })
+            .collect()
+    }
+
+    /// Duty cycle series of the recorded temperatures through the controller configured by
+    /// `cli_args`, applied to a PWM backend without hardware. Each curve reads the recorded
+    /// temperature of its column in `columns`, the replayed rows have them in curve order.
+    pub fn replay(
+        cli_args: &impl CliArgsTrait,
+        columns: &[usize],
+        rows: &[HistoryRow],
+    ) -> Vec<HistoryRow> {
+        let mut controller = FanController::new(cli_args);
+        let pwm_manager = NullPwmManager::default();
+        let Some(first) = rows.first() else {
+            return Vec::new();
+        };
+        let clock = ReplayClock {
+            now: Cell::new(first.get_timestamp()),
+        };
+        let mut quiet_schedule = QuietSchedule::new(&clock, cli_args.get_quiet_profiles());
+        // recorded offsets of the wall clock, overrides expire on the monotonic clock from here
+        let start = Instant::now();
+
+        rows.iter()
+            .map(|row| {
+                clock.now.set(row.get_timestamp());
+                let temps: Vec<u8> = columns.iter().map(|&i| row.get_temps()[i]).collect();
+                let elapsed = (row.get_timestamp() - first.get_timestamp())
+                    .to_std()
+                    .unwrap_or_default();
+                let step = controller.step(
+                    start + elapsed,
+                    ControlInput {
+                        temps: &temps,
+                        quiet: quiet_schedule.update(),
+                        ..Default::default()
+                    },
+                    &pwm_manager,
+                );
+                let applied = step.get_applied().map(|_| pwm_manager.get_duty());
+                HistoryRow::new(
+                    row.get_timestamp(),
+                    temps,
+                    step.target,
+                    applied,
+                    None,
+                    step.mode,
+                )
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::cli_arguments::cli_args::{MockArgs, MockCliArgsTrait};
+        use crate::controller::fan_controller::FanMode;
+
+        fn cli_mock(quiet: &str) -> MockCliArgsTrait {
+            MockArgs {
+                quiet_profiles: vec![quiet.parse().unwrap()],
+                ..Default::default()
+            }
+            .mock()
+        }
+
+        fn row(time: &str, temp: u8) -> HistoryRow {
+            HistoryRow::new(
+                DateTime::parse_from_rfc3339(time).unwrap(),
+                vec![40, temp],
+                1.0,
+                Some(1.0),
+                Some(3000),
+                FanMode::Auto,
+            )
+        }
+
+        #[test]
+        fn test_replay() {
+            let rows = [
+                row("2026-06-01T21:59:00+02:00", 60),
+                row("2026-06-01T22:00:00+02:00", 60),
+                row("2026-06-01T22:01:00+02:00", 80),
+            ];
+            let replayed = replay(&cli_mock("22:00-07:00,40"), &[1], &rows);
+
+            let csv: Vec<String> = replayed.iter().map(HistoryRow::to_csv).collect();
+            // the recorded RPM doesn't apply to the replayed duty
+            assert_eq!(
+                csv,
+                vec![
+                    "2026-06-01T21:59:00.000+02:00,60,0.600,0.600,,auto",
+                    "2026-06-01T22:00:00.000+02:00,60,0.400,0.400,,quiet",
+                    // critical temperature, the quiet profile doesn't apply
+                    "2026-06-01T22:01:00.000+02:00,80,1.000,1.000,,auto",
+                ]
+            );
+        }
+
+        #[test]
+        fn test_sensor_columns() {
+            let cli_mock = cli_mock("22:00-07:00,40");
+            assert_eq!(
+                sensor_columns(&cli_mock, &["nvme".to_string(), "cpu".to_string()]),
+                Ok(vec![1])
+            );
+            assert!(sensor_columns(&cli_mock, &["nvme".to_string()]).is_err());
+        }
+    }
+}
@@ -0,0 +1,442 @@
+pub mod tick_scheduler {
+    use log::{debug, warn};
+    use std::thread;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    /// Shortest period accepted between two updates, to avoid busy-looping on sysfs reads.
+    pub const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Source of monotonic time, injectable to test scheduling without sleeping.
+    pub trait Clock {
+        fn now(&self) -> Instant;
+
+        fn sleep(&self, duration: Duration);
+    }
+
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct MonotonicClock;
+
+    impl Clock for MonotonicClock {
+        fn now(&self) -> Instant {
+            Instant::now()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            thread::sleep(duration);
+        }
+    }
+
+    /// Fires ticks every `period` on a fixed grid of the monotonic clock, so that slow
+    /// iterations don't drift the period. Each tick can be delayed by a random jitter.
+    pub struct TickScheduler<C: Clock> {
+        clock: C,
+        period: Duration,
+        jitter: Duration,
+        next: Instant,
+        rng: u64,
+    }
+
+    impl<C: Clock> TickScheduler<C> {
+        /// First tick fires immediately.
+        pub fn new(clock: C, period: Duration, jitter: Duration) -> Self {
+            let next = clock.now();
+            // xorshift seed, never zero
+            let rng = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64)
+                | 1;
+            TickScheduler {
+                clock,
+                period,
+                jitter,
+                next,
+                rng,
+            }
+        }
+
+        /// Change the period, the next tick is moved relative to the last one.
+        pub fn set_period(&mut self, period: Duration) {
+            if period == self.period {
+                return;
+            }
+            if let Some(last) = self.next.checked_sub(self.period) {
+                self.next = last + period;
+            }
+            debug!("Period changed from {:?} to {:?}", self.period, period);
+            self.period = period;
+        }
+
+        /// Sleep until the next tick.
+        pub fn wait_next_tick(&mut self) {
+            let target = self.next + self.next_jitter();
+            let now = self.clock.now();
+            if target > now {
+                self.clock.sleep(target - now);
+            }
+
+            // next point of the grid after now, skipping ticks missed by a slow iteration
+            self.next += self.period;
+            let now = self.clock.now();
+            if self.next <= now {
+                let behind = now - self.next;
+                let missed = behind.as_nanos() / self.period.as_nanos() + 1;
+                warn!("Update late by {:?}, skipping {} tick(s)", behind, missed);
+                self.next += self.period * missed as u32;
+            }
+            debug!("Next tick in {:?}", self.next - now);
+        }
+
+        fn next_jitter(&mut self) -> Duration {
+            if self.jitter.is_zero() {
+                return Duration::ZERO;
+            }
+            self.rng ^= self.rng << 13;
+            self.rng ^= self.rng >> 7;
+            self.rng ^= self.rng << 17;
+            Duration::from_nanos(self.rng % self.jitter.as_nanos() as u64)
+        }
+    }
+
+    /// Parse a duration as a number followed by `ms`, `s`, `m` or `h`, e.g. `500ms`, `2s`, `1m`.
+    /// A number without unit is in seconds.
+    pub fn parse_duration(s: &str) -> Result<Duration, String> {
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(s.len());
+        let (value, unit) = s.split_at(split);
+        let value: f64 = value
+            .parse()
+            .map_err(|_| format!("`{s}` isn't a valid duration"))?;
+        let secs = match unit.trim() {
+            "ms" => value / 1000.0,
+            "" | "s" => value,
+            "m" => value * 60.0,
+            "h" => value * 3600.0,
+            _ => return Err(format!("`{s}` has an unknown unit, use ms, s, m or h")),
+        };
+        Duration::try_from_secs_f64(secs).map_err(|_| format!("`{s}` isn't a valid duration"))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::cell::{Cell, RefCell};
+
+        // clock moving only when sleeping or explicitly advanced
+        struct FakeClock {
+            now: Cell<Instant>,
+            sleeps: RefCell<Vec<Duration>>,
+        }
+
+        impl FakeClock {
+            fn new() -> Self {
+                FakeClock {
+                    now: Cell::new(Instant::now()),
+                    sleeps: RefCell::new(Vec::new()),
+                }
+            }
+
+            fn advance(&self, duration: Duration) {
+                self.now.set(self.now.get() + duration);
+            }
+        }
+
+        impl Clock for &FakeClock {
+            fn now(&self) -> Instant {
+                self.now.get()
+            }
+
+            fn sleep(&self, duration: Duration) {
+                self.sleeps.borrow_mut().push(duration);
+                self.advance(duration);
+            }
+        }
+
+        #[test]
+        fn test_parse_duration() {
+            assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+            assert_eq!(parse_duration("2s"), Ok(Duration::from_secs(2)));
+            assert_eq!(parse_duration("1m"), Ok(Duration::from_secs(60)));
+            assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+            assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+            assert_eq!(parse_duration("3"), Ok(Duration::from_secs(3)));
+        }
+
+        #[test]
+        fn test_parse_duration_invalid() {
+            assert!(parse_duration("").is_err());
+            assert!(parse_duration("ms").is_err());
+            assert!(parse_duration("2d").is_err());
+            assert!(parse_duration("-1s").is_err());
+        }
+
+        #[test]
+        fn test_fixed_period() {
+            let clock = FakeClock::new();
+            let mut scheduler = TickScheduler::new(&clock, Duration::from_secs(1), Duration::ZERO);
+
+            // first tick is immediate
+            scheduler.wait_next_tick();
+            assert!(clock.sleeps.borrow().is_empty());
+
+            for _ in 0..3 {
+                scheduler.wait_next_tick();
+            }
+            assert_eq!(*clock.sleeps.borrow(), vec![Duration::from_secs(1); 3]);
+        }
+
+        #[test]
+        fn test_slow_iteration_does_not_drift() {
+            let clock = FakeClock::new();
+            let start = clock.now.get();
+            let mut scheduler = TickScheduler::new(&clock, Duration::from_secs(1), Duration::ZERO);
+
+            scheduler.wait_next_tick();
+            // reading sensors took 300ms
+            clock.advance(Duration::from_millis(300));
+            scheduler.wait_next_tick();
+            assert_eq!(clock.now.get() - start, Duration::from_secs(1));
+        }
+
+        #[test]
+        fn test_overrun_skips_missed_ticks() {
+            let clock = FakeClock::new();
+            let start = clock.now.get();
+            let mut scheduler = TickScheduler::new(&clock, Duration::from_secs(1), Duration::ZERO);
+
+            scheduler.wait_next_tick();
+            scheduler.wait_next_tick();
+            // iteration stuck for 2.5s, the late tick fires at once and the one at 3s is skipped
+            clock.advance(Duration::from_millis(2500));
+            scheduler.wait_next_tick();
+            assert_eq!(clock.now.get() - start, Duration::from_millis(3500));
+            scheduler.wait_next_tick();
+            assert_eq!(clock.now.get() - start, Duration::from_secs(4));
+        }
+
+        #[test]
+        fn test_set_period() {
+            let clock = FakeClock::new();
+            let start = clock.now.get();
+            let mut scheduler = TickScheduler::new(&clock, Duration::from_secs(10), Duration::ZERO);
+
+            scheduler.wait_next_tick();
+            scheduler.set_period(Duration::from_secs(1));
+            scheduler.wait_next_tick();
+            assert_eq!(clock.now.get() - start, Duration::from_secs(1));
+
+            scheduler.set_period(Duration::from_secs(5));
+            scheduler.wait_next_tick();
+            assert_eq!(clock.now.get() - start, Duration::from_secs(6));
+        }
+
+        #[test]
+        fn test_jitter_bounds() {
+            let clock = FakeClock::new();
+            let start = clock.now.get();
+            let mut scheduler =
+                TickScheduler::new(&clock, Duration::from_secs(1), Duration::from_millis(200));
+
+            for i in 0..20u32 {
+                scheduler.wait_next_tick();
+                let elapsed = clock.now.get() - start;
+                assert!(elapsed >= Duration::from_secs(i.into()));
+                assert!(elapsed < Duration::from_secs(i.into()) + Duration::from_millis(200));
+            }
+        }
+    }
+}
+
+pub mod adaptive_interval {
+    use log::debug;
+    use std::collections::VecDeque;
+    use std::time::{Duration, Instant};
+
+    /// Rate of change, in Celsius per second, considered fast enough to poll at the minimum interval.
+    pub const FAST_RATE: f64 = 0.5;
+
+    /// Rate of change, in Celsius per second, below which the temperature is considered stable.
+    pub const STABLE_RATE: f64 = 0.05;
+
+    /// Distance in Celsius from a curve step below which polling gets faster.
+    pub const STEP_MARGIN: u8 = 2;
+
+    /// Readings kept to measure slow trends, whose changes between two updates are
+    /// hidden by the whole degree resolution.
+    pub const RATE_WINDOW: Duration = Duration::from_secs(30);
+
+    /// Polling interval adapting to the temperature trend: the minimum when the temperature
+    /// changes fast, shorter when changing close to a curve step, longer when stable.
+    #[derive(Debug)]
+    pub struct AdaptiveInterval {
+        min: Duration,
+        max: Duration,
+        current: Duration,
+        samples: VecDeque<(Instant, Vec<u8>)>,
+    }
+
+    impl AdaptiveInterval {
+        pub fn new(min: Duration, max: Duration, start: Duration) -> Self {
+            AdaptiveInterval {
+                min,
+                max,
+                current: start.clamp(min, max),
+                samples: VecDeque::new(),
+            }
+        }
+
+        /// Compute the next interval from the temperatures read at `now` and the distance
+        /// of the closest temperature to a curve step.
+        pub fn update(&mut self, now: Instant, temps: &[u8], step_distance: u8) -> Duration {
+            // fastest change against the readings of the window, a 1°C difference is the
+            // rounding of the readings and doesn't count as a change
+            let rate = self
+                .samples
+                .iter()
+                .filter(|(time, _)| now > *time)
+                .map(|(time, past)| {
+                    let elapsed = (now - *time).as_secs_f64();
+                    temps
+                        .iter()
+                        .zip(past)
+                        .map(|(&t, &p)| f64::from(t.abs_diff(p).saturating_sub(1)) / elapsed)
+                        .fold(0.0, f64::max)
+                })
+                .reduce(f64::max)
+                // no trend yet
+                .unwrap_or((FAST_RATE + STABLE_RATE) / 2.0);
+
+            self.samples.push_back((now, temps.to_vec()));
+            // the oldest reading kept is the newest one at least RATE_WINDOW old
+            while self
+                .samples
+                .get(1)
+                .is_some_and(|(time, _)| now.saturating_duration_since(*time) >= RATE_WINDOW)
+            {
+                self.samples.pop_front();
+            }
+
+            let near_step = step_distance <= STEP_MARGIN;
+            self.current = if rate >= FAST_RATE {
+                self.min
+            } else if rate < STABLE_RATE {
+                self.current.mul_f64(1.5).min(self.max)
+            } else if near_step {
+                (self.current / 2).max(self.min)
+            } else {
+                self.current
+            };
+
+            debug!(
+                "rate {:.2} C/s, step distance {} C: interval {:?}",
+                rate, step_distance, self.current
+            );
+            self.current
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_stable_lengthens() {
+            let start = Instant::now();
+            let mut interval = AdaptiveInterval::new(
+                Duration::from_secs(1),
+                Duration::from_secs(10),
+                Duration::from_secs(2),
+            );
+
+            // no trend on first reading
+            assert_eq!(interval.update(start, &[45], 10), Duration::from_secs(2));
+            let mut now = start;
+            for _ in 0..10 {
+                now += Duration::from_secs(3);
+                interval.update(now, &[45], 10);
+            }
+            assert_eq!(
+                interval.update(now + Duration::from_secs(10), &[45], 10),
+                Duration::from_secs(10)
+            );
+        }
+
+        #[test]
+        fn test_fast_rise_goes_to_min() {
+            let start = Instant::now();
+            let mut interval = AdaptiveInterval::new(
+                Duration::from_millis(500),
+                Duration::from_secs(10),
+                Duration::from_secs(10),
+            );
+
+            interval.update(start, &[45, 40], 10);
+            // second sensor rose 10 C in 10 s
+            assert_eq!(
+                interval.update(start + Duration::from_secs(10), &[45, 50], 10),
+                Duration::from_millis(500)
+            );
+        }
+
+        #[test]
+        fn test_near_step_shortens() {
+            let start = Instant::now();
+            let mut interval = AdaptiveInterval::new(
+                Duration::from_secs(1),
+                Duration::from_secs(10),
+                Duration::from_secs(8),
+            );
+
+            assert_eq!(interval.update(start, &[46], 2), Duration::from_secs(4));
+            // rising about 0.3 C/s towards the step at 50 C
+            assert_eq!(
+                interval.update(start + Duration::from_secs(4), &[48], 2),
+                Duration::from_secs(2)
+            );
+            assert_eq!(
+                interval.update(start + Duration::from_secs(6), &[49], 1),
+                Duration::from_secs(1)
+            );
+            assert_eq!(
+                interval.update(start + Duration::from_secs(7), &[50], 0),
+                Duration::from_secs(1)
+            );
+        }
+
+        #[test]
+        fn test_stable_on_step_lengthens() {
+            let mut now = Instant::now();
+            let mut interval = AdaptiveInterval::new(
+                Duration::from_millis(500),
+                Duration::from_secs(10),
+                Duration::from_millis(500),
+            );
+
+            // idle on the 50 C step, the reading flickering by one degree
+            let mut current = interval.update(now, &[50], 0);
+            for i in 0..30 {
+                now += current;
+                current = interval.update(now, &[50 + i % 2], 0);
+            }
+            assert_eq!(current, Duration::from_secs(10));
+        }
+
+        #[test]
+        fn test_slow_change_keeps_interval() {
+            let start = Instant::now();
+            let mut interval = AdaptiveInterval::new(
+                Duration::from_secs(1),
+                Duration::from_secs(10),
+                Duration::from_secs(4),
+            );
+
+            interval.update(start, &[45], 10);
+            // 2 C in 4 s
+            assert_eq!(
+                interval.update(start + Duration::from_secs(4), &[47], 10),
+                Duration::from_secs(4)
+            );
+        }
+    }
+}
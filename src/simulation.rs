@@ -0,0 +1,357 @@
+pub mod thermal_simulation {
+    use crate::cli_arguments::cli_args::CliArgsTrait;
+    use crate::controller::fan_controller::{ControlInput, FanController};
+    use crate::history::history_recorder::HistoryRow;
+    use crate::pwm::pwm_manager::NullPwmManager;
+    use crate::quiet::quiet_schedule::{LocalClock, QuietSchedule};
+    use crate::scheduler::adaptive_interval::AdaptiveInterval;
+    use crate::scheduler::tick_scheduler::{parse_duration, Clock, TickScheduler};
+    use chrono::{DateTime, FixedOffset};
+    use std::cell::Cell;
+    use std::str::FromStr;
+    use std::time::{Duration, Instant};
+
+    /// Longest integration step of the model.
+    const MAX_STEP: Duration = Duration::from_millis(100);
+    /// Shortest integration step, whatever the time constant.
+    const MIN_STEP: Duration = Duration::from_micros(1);
+    /// Integration steps stay below this fraction of the time constant, for the explicit
+    /// Euler integration to converge.
+    const STEP_FRACTION: f64 = 0.1;
+
+    /// Heat input over time, the last segment lasts until the end of the simulation.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct LoadProfile {
+        segments: Vec<(Duration, f64)>,
+    }
+
+    impl LoadProfile {
+        /// Power in watts after `elapsed` from the start.
+        pub fn power_at(&self, elapsed: Duration) -> f64 {
+            let mut end = Duration::ZERO;
+            for (duration, watts) in &self.segments {
+                end += *duration;
+                if elapsed < end {
+                    return *watts;
+                }
+            }
+            self.segments.last().map_or(0.0, |(_, watts)| *watts)
+        }
+    }
+
+    // Format: DURATION:WATTS,..., e.g. 1m:3,5m:8,4m:3
+    impl FromStr for LoadProfile {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let segments = s
+                .split(',')
+                .map(|segment| {
+                    let (duration, watts) = segment
+                        .trim()
+                        .split_once(':')
+                        .ok_or_else(|| format!("`{segment}` isn't in the DURATION:WATTS format"))?;
+                    let watts = watts
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|w| w.is_finite() && *w >= 0.0)
+                        .ok_or_else(|| format!("`{watts}` isn't a power in watts"))?;
+                    Ok((parse_duration(duration)?, watts))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok(LoadProfile { segments })
+        }
+    }
+
+    /// First-order thermal model of the SoC: the load heats a single thermal mass, cooled
+    /// towards ambient through the heatsink and proportionally to the fan duty cycle.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ThermalModel {
+        temp: f64,
+        ambient: f64,
+        // J/K
+        heat_capacity: f64,
+        // W/K without airflow
+        passive_conductance: f64,
+        // W/K added at full fan speed
+        fan_conductance: f64,
+    }
+
+    impl ThermalModel {
+        /// Model starting at the ambient temperature.
+        pub fn new(
+            ambient: f64,
+            heat_capacity: f64,
+            passive_conductance: f64,
+            fan_conductance: f64,
+        ) -> Self {
+            ThermalModel {
+                temp: ambient,
+                ambient,
+                heat_capacity,
+                passive_conductance,
+                fan_conductance,
+            }
+        }
+
+        pub fn get_temp(&self) -> f64 {
+            self.temp
+        }
+
+        /// Longest stable integration step, a fraction of the time constant at full fan speed.
+        pub fn max_step(&self) -> Duration {
+            let conductance = self.passive_conductance + self.fan_conductance;
+            if conductance <= 0.0 {
+                return MAX_STEP;
+            }
+            Duration::try_from_secs_f64(STEP_FRACTION * self.heat_capacity / conductance)
+                .map_or(MAX_STEP, |step| step.clamp(MIN_STEP, MAX_STEP))
+        }
+
+        /// Integrate `dt` with `power` watts of load and the fan at `duty`, in 0.0..=1.0 .
+        pub fn step(&mut self, dt: Duration, power: f64, duty: f64) {
+            let conductance =
+                self.passive_conductance + self.fan_conductance * duty.clamp(0.0, 1.0);
+            let cooling = conductance * (self.temp - self.ambient);
+            self.temp += (power - cooling) * dt.as_secs_f64() / self.heat_capacity;
+        }
+    }
+
+    /// Simulated time, advanced by the scheduler sleeps instead of waiting.
+    struct SimulatedClock {
+        start: Instant,
+        wall_start: DateTime<FixedOffset>,
+        elapsed: Cell<Duration>,
+    }
+
+    impl Clock for &SimulatedClock {
+        fn now(&self) -> Instant {
+            self.start + self.elapsed.get()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.elapsed.set(self.elapsed.get() + duration);
+        }
+    }
+
+    impl LocalClock for &SimulatedClock {
+        fn now(&self) -> DateTime<FixedOffset> {
+            self.wall_start + self.elapsed.get()
+        }
+    }
+
+    /// Run the control loop configured by `cli_args` for `duration` against `model`, which is
+    /// both the temperature of every sensor and the sink of the applied duty cycle.
+    /// Time starts at `wall_start`, the local time quiet profiles are evaluated with.
+    pub fn simulate(
+        cli_args: &impl CliArgsTrait,
+        mut model: ThermalModel,
+        load: &LoadProfile,
+        duration: Duration,
+        wall_start: DateTime<FixedOffset>,
+    ) -> Vec<HistoryRow> {
+        let clock = SimulatedClock {
+            start: Instant::now(),
+            wall_start,
+            elapsed: Cell::new(Duration::ZERO),
+        };
+        // no jitter, runs are reproducible
+        let mut scheduler = TickScheduler::new(&clock, cli_args.get_interval(), Duration::ZERO);
+        let mut adaptive_interval = cli_args.is_adaptive_interval().then(|| {
+            AdaptiveInterval::new(
+                cli_args.get_min_interval(),
+                cli_args.get_max_interval(),
+                cli_args.get_interval(),
+            )
+        });
+        let mut controller = FanController::new(cli_args);
+        let mut quiet_schedule = QuietSchedule::new(&clock, cli_args.get_quiet_profiles());
+        let pwm_manager = NullPwmManager::default();
+
+        let mut rows = Vec::new();
+        let max_step = model.max_step();
+        let mut last = Duration::ZERO;
+        loop {
+            scheduler.wait_next_tick();
+            let elapsed = clock.elapsed.get();
+            if elapsed > duration {
+                break;
+            }
+            // the plant evolves with the duty applied at the previous update
+            while last < elapsed {
+                let dt = (elapsed - last).min(max_step);
+                model.step(dt, load.power_at(last), pwm_manager.get_duty());
+                last += dt;
+            }
+
+            let temp = model.get_temp().round().clamp(0.0, f64::from(u8::MAX)) as u8;
+            let temps = vec![temp; controller.get_curves().len()];
+            let step = controller.step(
+                Clock::now(&&clock),
+                ControlInput {
+                    temps: &temps,
+                    quiet: quiet_schedule.update(),
+                    ..Default::default()
+                },
+                &pwm_manager,
+            );
+            rows.push(HistoryRow::new(
+                LocalClock::now(&&clock),
+                temps.clone(),
+                step.target,
+                step.get_applied().map(|_| pwm_manager.get_duty()),
+                None,
+                step.mode,
+            ));
+
+            if let Some(adaptive_interval) = adaptive_interval.as_mut() {
+                let step_distance = controller
+                    .get_curves()
+                    .iter()
+                    .map(|sc| sc.get_curve().distance_to_step(temp))
+                    .min()
+                    .unwrap_or(u8::MAX);
+                scheduler.set_period(adaptive_interval.update(
+                    Clock::now(&&clock),
+                    &temps,
+                    step_distance,
+                ));
+            }
+        }
+        rows
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::cli_arguments::cli_args::{MockArgs, MockCliArgsTrait};
+        use crate::curve::fan_curve::{FanCurve, SensorCurve};
+
+        fn cli_mock(manual_speed: Option<u8>, adaptive: bool) -> MockCliArgsTrait {
+            let mut cli_mock = MockArgs {
+                curves: vec![SensorCurve::new(
+                    "cpu".to_string(),
+                    FanCurve::new(vec![50, 60, 70], vec![20, 60, 100]),
+                    1.0,
+                )],
+                manual_speed,
+                ..Default::default()
+            }
+            .mock();
+            cli_mock
+                .expect_get_interval()
+                .returning(|| Duration::from_secs(1));
+            cli_mock
+                .expect_is_adaptive_interval()
+                .returning(move || adaptive);
+            cli_mock
+                .expect_get_min_interval()
+                .returning(|| Duration::from_millis(500));
+            cli_mock
+                .expect_get_max_interval()
+                .returning(|| Duration::from_secs(10));
+            cli_mock
+        }
+
+        fn model() -> ThermalModel {
+            ThermalModel::new(25.0, 20.0, 0.1, 0.3)
+        }
+
+        fn start() -> DateTime<FixedOffset> {
+            DateTime::parse_from_rfc3339("2026-06-01T12:00:00+02:00").unwrap()
+        }
+
+        fn max_temp(rows: &[HistoryRow]) -> u8 {
+            rows.iter().map(|r| r.get_temps()[0]).max().unwrap()
+        }
+
+        #[test]
+        fn test_load_profile() {
+            let load: LoadProfile = "1m:3,30s:8.5".parse().unwrap();
+            assert_eq!(load.power_at(Duration::ZERO), 3.0);
+            assert_eq!(load.power_at(Duration::from_secs(60)), 8.5);
+            assert_eq!(load.power_at(Duration::from_secs(3600)), 8.5);
+            assert!("1m".parse::<LoadProfile>().is_err());
+            assert!("1m:-2".parse::<LoadProfile>().is_err());
+            assert!("forever:2".parse::<LoadProfile>().is_err());
+        }
+
+        #[test]
+        fn test_model_steady_state() {
+            // steady state at ambient + power / conductance
+            let mut model = model();
+            for _ in 0..20_000 {
+                model.step(Duration::from_millis(100), 8.0, 0.0);
+            }
+            assert!((model.get_temp() - 105.0).abs() < 0.1);
+            for _ in 0..20_000 {
+                model.step(Duration::from_millis(100), 8.0, 1.0);
+            }
+            assert!((model.get_temp() - 45.0).abs() < 0.1);
+        }
+
+        #[test]
+        fn test_max_step() {
+            assert_eq!(model().max_step(), MAX_STEP);
+            // 20 J/K cooled by 1000 W/K: 2 ms steps instead of 100 ms
+            let model = ThermalModel::new(25.0, 20.0, 0.0, 1000.0);
+            assert_eq!(model.max_step(), Duration::from_millis(2));
+            assert_eq!(ThermalModel::new(25.0, 20.0, 0.0, 0.0).max_step(), MAX_STEP);
+        }
+
+        #[test]
+        fn test_strong_cooling_converges() {
+            let load: LoadProfile = "10m:8".parse().unwrap();
+            let model = ThermalModel::new(25.0, 20.0, 500.0, 500.0);
+            let rows = simulate(
+                &cli_mock(None, false),
+                model,
+                &load,
+                Duration::from_secs(600),
+                start(),
+            );
+            assert!(rows.iter().all(|r| (25..=60).contains(&r.get_temps()[0])));
+        }
+
+        #[test]
+        fn test_curve_limits_temperature() {
+            let load: LoadProfile = "30s:2,10m:8".parse().unwrap();
+            let duration = Duration::from_secs(600);
+
+            let rows = simulate(&cli_mock(None, false), model(), &load, duration, start());
+            assert_eq!(rows.len(), 601);
+            assert_eq!(rows[0].get_timestamp(), start());
+            assert_eq!(
+                rows[600].get_timestamp(),
+                start() + Duration::from_secs(600)
+            );
+            // the curve settles between its steps, the fan at 20% would reach 75°C
+            assert!(max_temp(&rows) <= 60);
+            assert!(rows[600].get_target_duty() > 0.2);
+
+            let slow = simulate(
+                &cli_mock(Some(20), false),
+                model(),
+                &load,
+                duration,
+                start(),
+            );
+            assert!(max_temp(&slow) > 65);
+        }
+
+        #[test]
+        fn test_adaptive_interval() {
+            let load: LoadProfile = "10m:2".parse().unwrap();
+            let duration = Duration::from_secs(600);
+
+            let rows = simulate(&cli_mock(None, true), model(), &load, duration, start());
+            // stable temperature, polling slows down to the maximum interval
+            assert!(rows.len() < 200);
+            let last = &rows[rows.len() - 2..];
+            assert_eq!(
+                last[1].get_timestamp() - last[0].get_timestamp(),
+                chrono::Duration::seconds(10)
+            );
+        }
+    }
+}
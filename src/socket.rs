@@ -0,0 +1,579 @@
+pub mod control_socket {
+    use crate::control::control_command::ControlCommand;
+    use crate::curve::fan_curve::SensorCurve;
+    use crate::metrics::fan_metrics::SharedMetrics;
+    use crate::scheduler::tick_scheduler::parse_duration;
+    use log::{debug, error, info, warn};
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+    use std::fs;
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc::Sender;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// One request per line, e.g. `{"command":"set-manual","speed":60,"duration":"10m"}`.
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    #[serde(tag = "command", rename_all = "kebab-case")]
+    pub enum Request {
+        Status,
+        /// Without duration the override lasts until cleared
+        SetManual {
+            speed: u8,
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            duration: Option<String>,
+        },
+        ClearManual,
+        /// Curve in the `--curve` format, replacing the one of the same sensor
+        SetCurve {
+            curve: String,
+        },
+        SetProfile {
+            profile: String,
+        },
+        Reload,
+    }
+
+    /// One response line for each request.
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct Response {
+        pub ok: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub error: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub status: Option<Status>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct SensorTemperature {
+        pub sensor: String,
+        pub celsius: u8,
+    }
+
+    /// Snapshot of the last control loop iteration.
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct Status {
+        pub temperatures: Vec<SensorTemperature>,
+        pub curves: Vec<String>,
+        #[serde(default)]
+        pub profile: Option<String>,
+        /// Names of the profiles the daemon can switch to
+        #[serde(default)]
+        pub profiles: Vec<String>,
+        pub target_duty: f64,
+        pub applied_duty: f64,
+        pub rpm: Option<u32>,
+        pub mode: String,
+        /// Seconds before the manual override expires
+        #[serde(default)]
+        pub manual_remaining_secs: Option<u64>,
+        pub uptime_secs: u64,
+    }
+
+    impl Response {
+        fn ok() -> Self {
+            Response {
+                ok: true,
+                error: None,
+                status: None,
+            }
+        }
+
+        fn error(msg: String) -> Self {
+            Response {
+                ok: false,
+                error: Some(msg),
+                status: None,
+            }
+        }
+    }
+
+    /// Request carrying a command to the daemon, e.g. from a client holding a `ControlCommand`.
+    impl From<ControlCommand> for Request {
+        fn from(command: ControlCommand) -> Self {
+            match command {
+                ControlCommand::SetManual(speed) => Request::SetManual {
+                    speed,
+                    duration: None,
+                },
+                ControlCommand::SetManualFor(speed, duration) => Request::SetManual {
+                    speed,
+                    duration: Some(format!("{}s", duration.as_secs())),
+                },
+                ControlCommand::ClearManual => Request::ClearManual,
+                ControlCommand::SetCurve(sensor_curve) => Request::SetCurve {
+                    curve: sensor_curve.to_string(),
+                },
+                ControlCommand::SetProfile(profile) => Request::SetProfile { profile },
+                ControlCommand::Reload => Request::Reload,
+            }
+        }
+    }
+
+    impl fmt::Display for Status {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self.manual_remaining_secs {
+                Some(secs) => writeln!(f, "Mode:    {} ({}s left)", self.mode, secs)?,
+                None => writeln!(f, "Mode:    {}", self.mode)?,
+            }
+            writeln!(
+                f,
+                "Duty:    {:.0}% (target {:.0}%)",
+                self.applied_duty * 100.0,
+                self.target_duty * 100.0
+            )?;
+            match self.rpm {
+                Some(rpm) => writeln!(f, "RPM:     {rpm}")?,
+                None => writeln!(f, "RPM:     n/a")?,
+            }
+            let uptime = self.uptime_secs;
+            writeln!(
+                f,
+                "Uptime:  {}h {:02}m {:02}s",
+                uptime / 3600,
+                uptime / 60 % 60,
+                uptime % 60
+            )?;
+            writeln!(f, "Temperatures:")?;
+            for temperature in &self.temperatures {
+                writeln!(f, "  {}: {}°C", temperature.sensor, temperature.celsius)?;
+            }
+            if let Some(profile) = &self.profile {
+                writeln!(f, "Profile: {profile}")?;
+            }
+            writeln!(f, "Curves:")?;
+            for curve in &self.curves {
+                writeln!(f, "  {curve}")?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Send one request to the running daemon and wait for its response.
+    pub fn send(path: &Path, request: &Request) -> Result<Response, io::Error> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        let mut line = serde_json::to_string(request).map_err(io::Error::other)?;
+        line.push('\n');
+        (&stream).write_all(line.as_bytes())?;
+
+        let mut response = String::new();
+        BufReader::new(&stream).read_line(&mut response)?;
+        serde_json::from_str(&response).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Control socket served on a background thread, the file is removed on drop.
+    #[derive(Debug)]
+    pub struct ControlSocket {
+        path: PathBuf,
+    }
+
+    impl ControlSocket {
+        /// Bind the socket, readable and writable by the owner and by `group` when given.
+        pub fn start(
+            path: &Path,
+            group: Option<&str>,
+            metrics: SharedMetrics,
+            commands: Sender<ControlCommand>,
+        ) -> Result<Self, io::Error> {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            remove_stale_socket(path)?;
+
+            let listener = UnixListener::bind(path)?;
+            let socket = ControlSocket {
+                path: path.to_path_buf(),
+            };
+
+            match group {
+                Some(group) => {
+                    let gid = resolve_group(group)?;
+                    std::os::unix::fs::chown(path, None, Some(gid))?;
+                    fs::set_permissions(path, fs::Permissions::from_mode(0o660))?;
+                }
+                None => fs::set_permissions(path, fs::Permissions::from_mode(0o600))?,
+            }
+            info!("Control socket listening on {}", path.display());
+
+            let started = Instant::now();
+            thread::Builder::new()
+                .name("control-socket".to_string())
+                .spawn(move || {
+                    for stream in listener.incoming() {
+                        match stream {
+                            Ok(stream) => {
+                                let metrics = metrics.clone();
+                                let commands = commands.clone();
+                                // a slow client must not block the others
+                                let _ = thread::Builder::new()
+                                    .name("control-client".to_string())
+                                    .spawn(move || {
+                                        if let Err(e) =
+                                            handle_connection(stream, &metrics, &commands, started)
+                                        {
+                                            debug!("Control connection failed: {}", e);
+                                        }
+                                    });
+                            }
+                            Err(e) => error!("Control socket accept failed: {}", e),
+                        }
+                    }
+                })?;
+            Ok(socket)
+        }
+    }
+
+    impl Drop for ControlSocket {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    // a socket left by a crashed daemon is removed, a live one is an error
+    fn remove_stale_socket(path: &Path) -> Result<(), io::Error> {
+        match fs::symlink_metadata(path) {
+            Ok(meta) if meta.file_type().is_socket() => {
+                if UnixStream::connect(path).is_ok() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("Control socket {} already in use", path.display()),
+                    ));
+                }
+                warn!("Removing stale control socket {}", path.display());
+                fs::remove_file(path)
+            }
+            Ok(_) => Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and isn't a socket", path.display()),
+            )),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn resolve_group(group: &str) -> Result<u32, io::Error> {
+        if let Ok(gid) = group.parse() {
+            return Ok(gid);
+        }
+        find_group_id(&fs::read_to_string("/etc/group")?, group).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("Unknown group `{group}`"))
+        })
+    }
+
+    // /etc/group lines are NAME:PASSWORD:GID:MEMBERS
+    fn find_group_id(contents: &str, group: &str) -> Option<u32> {
+        contents.lines().find_map(|line| {
+            let mut fields = line.split(':');
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(name), Some(_), Some(gid)) if name == group => gid.parse().ok(),
+                _ => None,
+            }
+        })
+    }
+
+    fn handle_connection(
+        stream: UnixStream,
+        metrics: &SharedMetrics,
+        commands: &Sender<ControlCommand>,
+        started: Instant,
+    ) -> Result<(), io::Error> {
+        stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+        let reader = BufReader::new(&stream);
+        let mut writer = &stream;
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => handle_request(request, metrics, commands, started),
+                Err(e) => Response::error(format!("Invalid request: {e}")),
+            };
+            let mut out = serde_json::to_string(&response).map_err(io::Error::other)?;
+            out.push('\n');
+            writer.write_all(out.as_bytes())?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn handle_request(
+        request: Request,
+        metrics: &SharedMetrics,
+        commands: &Sender<ControlCommand>,
+        started: Instant,
+    ) -> Response {
+        debug!("Control request: {:?}", request);
+        let command = match request {
+            Request::Status => {
+                let metrics = metrics.lock().unwrap();
+                return Response {
+                    status: Some(Status {
+                        temperatures: metrics
+                            .get_temperatures()
+                            .iter()
+                            .map(|(sensor, celsius)| SensorTemperature {
+                                sensor: sensor.clone(),
+                                celsius: *celsius,
+                            })
+                            .collect(),
+                        curves: metrics
+                            .get_curves()
+                            .iter()
+                            .map(|sc| sc.to_string())
+                            .collect(),
+                        profile: metrics.get_profile().map(str::to_string),
+                        profiles: metrics.get_profiles().to_vec(),
+                        target_duty: metrics.get_target_duty(),
+                        applied_duty: metrics.get_applied_duty(),
+                        rpm: metrics.get_rpm(),
+                        mode: metrics.get_mode().to_string(),
+                        manual_remaining_secs: metrics.get_manual_remaining().map(|d| d.as_secs()),
+                        uptime_secs: started.elapsed().as_secs(),
+                    }),
+                    ..Response::ok()
+                };
+            }
+            Request::SetManual { speed, duration } if (1..=100).contains(&speed) => {
+                match duration.as_deref().map(parse_duration) {
+                    None => ControlCommand::SetManual(speed),
+                    Some(Ok(duration)) => ControlCommand::SetManualFor(speed, duration),
+                    Some(Err(e)) => return Response::error(e),
+                }
+            }
+            Request::SetManual { speed, .. } => {
+                return Response::error(format!("Speed {speed} not in percentage range 1-100"))
+            }
+            Request::ClearManual => ControlCommand::ClearManual,
+            Request::SetCurve { curve } => match curve.parse::<SensorCurve>() {
+                Ok(sensor_curve) => {
+                    let known = metrics
+                        .lock()
+                        .unwrap()
+                        .get_curves()
+                        .iter()
+                        .any(|sc| sc.get_sensor() == sensor_curve.get_sensor());
+                    if !known {
+                        return Response::error(format!(
+                            "No curve for sensor {}",
+                            sensor_curve.get_sensor()
+                        ));
+                    }
+                    ControlCommand::SetCurve(sensor_curve)
+                }
+                Err(e) => return Response::error(e),
+            },
+            Request::SetProfile { profile } => {
+                if !metrics.lock().unwrap().get_profiles().contains(&profile) {
+                    return Response::error(format!("Unknown curve profile {profile}"));
+                }
+                ControlCommand::SetProfile(profile)
+            }
+            Request::Reload => ControlCommand::Reload,
+        };
+
+        match commands.send(command) {
+            Ok(_) => Response::ok(),
+            Err(_) => Response::error("Control loop stopped".to_string()),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::curve::fan_curve::FanCurve;
+        use crate::metrics::fan_metrics::Metrics;
+        use std::sync::mpsc;
+        use std::sync::{Arc, Mutex};
+
+        fn request(stream: &mut UnixStream, line: &str) -> Response {
+            writeln!(stream, "{line}").unwrap();
+            let mut response = String::new();
+            BufReader::new(&*stream).read_line(&mut response).unwrap();
+            serde_json::from_str(&response).unwrap()
+        }
+
+        #[test]
+        fn test_parse_request() {
+            assert_eq!(
+                serde_json::from_str::<Request>(r#"{"command":"set-manual","speed":60}"#).unwrap(),
+                Request::SetManual {
+                    speed: 60,
+                    duration: None
+                }
+            );
+            assert_eq!(
+                serde_json::from_str::<Request>(
+                    r#"{"command":"set-manual","speed":100,"duration":"10m"}"#
+                )
+                .unwrap(),
+                Request::SetManual {
+                    speed: 100,
+                    duration: Some("10m".to_string())
+                }
+            );
+            assert_eq!(
+                serde_json::from_str::<Request>(r#"{"command":"clear-manual"}"#).unwrap(),
+                Request::ClearManual
+            );
+            assert!(serde_json::from_str::<Request>(r#"{"command":"reboot"}"#).is_err());
+        }
+
+        #[test]
+        fn test_request_from_command() {
+            assert_eq!(
+                Request::from(ControlCommand::SetManualFor(60, Duration::from_secs(600))),
+                Request::SetManual {
+                    speed: 60,
+                    duration: Some("600s".to_string())
+                }
+            );
+            assert_eq!(
+                Request::from(ControlCommand::SetProfile("silent".to_string())),
+                Request::SetProfile {
+                    profile: "silent".to_string()
+                }
+            );
+        }
+
+        #[test]
+        fn test_status_display() {
+            let status = Status {
+                temperatures: vec![SensorTemperature {
+                    sensor: "cpu".to_string(),
+                    celsius: 61,
+                }],
+                curves: vec!["cpu:50,70:20,100:1".to_string()],
+                profile: Some("silent".to_string()),
+                profiles: vec!["silent".to_string()],
+                target_duty: 0.44,
+                applied_duty: 0.44,
+                rpm: None,
+                mode: "auto".to_string(),
+                manual_remaining_secs: None,
+                uptime_secs: 3723,
+            };
+            let out = status.to_string();
+            assert!(out.contains("Mode:    auto\n"));
+            assert!(out.contains("Duty:    44% (target 44%)\n"));
+            assert!(out.contains("RPM:     n/a\n"));
+            assert!(out.contains("Uptime:  1h 02m 03s\n"));
+            assert!(out.contains("  cpu: 61°C\n"));
+            assert!(out.contains("Profile: silent\n"));
+            assert!(out.contains("  cpu:50,70:20,100:1\n"));
+        }
+
+        #[test]
+        fn test_find_group_id() {
+            let contents = "root:x:0:\nvideo:x:44:pi\nfan:x:1001:pi,admin\n";
+            assert_eq!(find_group_id(contents, "fan"), Some(1001));
+            assert_eq!(find_group_id(contents, "video"), Some(44));
+            assert_eq!(find_group_id(contents, "gpio"), None);
+        }
+
+        #[test]
+        fn test_socket_commands() {
+            let dir =
+                std::env::temp_dir().join(format!("rpi-temp-fan-pwm-sock-{}", std::process::id()));
+            let path = dir.join("control.sock");
+
+            let metrics: SharedMetrics = Arc::new(Mutex::new(Metrics::default()));
+            metrics.lock().unwrap().set_curves(vec![SensorCurve::new(
+                "cpu".to_string(),
+                FanCurve::new(vec![50, 70], vec![20, 100]),
+                1.0,
+            )]);
+            metrics
+                .lock()
+                .unwrap()
+                .set_temperatures(vec![("cpu".to_string(), 58)]);
+            metrics
+                .lock()
+                .unwrap()
+                .set_profiles(None, vec!["silent".to_string()]);
+            let (tx, rx) = mpsc::channel();
+            let socket = ControlSocket::start(&path, None, metrics, tx).unwrap();
+            assert_eq!(
+                fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+                0o600
+            );
+
+            let status = send(&path, &Request::Status).unwrap().status.unwrap();
+            assert_eq!(status.temperatures[0].celsius, 58);
+            assert_eq!(status.curves, vec!["cpu:50,70:20,100:1".to_string()]);
+            assert_eq!(status.mode, "auto");
+
+            let mut stream = UnixStream::connect(&path).unwrap();
+            assert!(request(&mut stream, r#"{"command":"set-manual","speed":70}"#).ok);
+            assert!(!request(&mut stream, r#"{"command":"set-manual","speed":170}"#).ok);
+            assert!(
+                request(
+                    &mut stream,
+                    r#"{"command":"set-manual","speed":100,"duration":"10m"}"#
+                )
+                .ok
+            );
+            assert!(
+                !request(
+                    &mut stream,
+                    r#"{"command":"set-manual","speed":100,"duration":"soon"}"#
+                )
+                .ok
+            );
+            assert!(
+                request(
+                    &mut stream,
+                    r#"{"command":"set-curve","curve":"cpu:40,60:0,100"}"#
+                )
+                .ok
+            );
+            assert!(
+                !request(
+                    &mut stream,
+                    r#"{"command":"set-curve","curve":"gpu:40,60:0,100"}"#
+                )
+                .ok
+            );
+            assert!(
+                request(
+                    &mut stream,
+                    r#"{"command":"set-profile","profile":"silent"}"#
+                )
+                .ok
+            );
+            assert!(
+                !request(
+                    &mut stream,
+                    r#"{"command":"set-profile","profile":"turbo"}"#
+                )
+                .ok
+            );
+            assert!(request(&mut stream, r#"{"command":"reload"}"#).ok);
+            assert!(!request(&mut stream, "garbage").ok);
+
+            assert_eq!(
+                rx.try_iter().collect::<Vec<_>>(),
+                vec![
+                    ControlCommand::SetManual(70),
+                    ControlCommand::SetManualFor(100, Duration::from_secs(600)),
+                    ControlCommand::SetCurve("cpu:40,60:0,100".parse().unwrap()),
+                    ControlCommand::SetProfile("silent".to_string()),
+                    ControlCommand::Reload,
+                ]
+            );
+
+            // the socket file can't be taken over while in use
+            assert!(remove_stale_socket(&path).is_err());
+            drop(socket);
+            assert!(!path.exists());
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
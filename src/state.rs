@@ -0,0 +1,330 @@
+pub mod controller_state {
+    use crate::controller::fan_controller::ControllerSettings;
+    use crate::emergency::emergency_action::CriticalState;
+    use log::{debug, info, warn};
+    use std::fs;
+    use std::io::{self};
+    use std::path::Path;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    /// Version of the state file format, bumped on incompatible changes.
+    pub const STATE_VERSION: u32 = 1;
+
+    /// Controller state surviving process restarts and timer activations.
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct ControllerState {
+        last_duty: Option<f64>,
+        // seconds since UNIX epoch of the last save
+        saved_at: Option<u64>,
+        // seconds since UNIX epoch, critical temperature reached and actions last run
+        critical_since: Option<u64>,
+        critical_fired: Option<u64>,
+        freq_limited: bool,
+        // seconds since UNIX epoch of the expiries of the manual overrides
+        manual_speed_until: Option<u64>,
+        manual_override: Option<u8>,
+        manual_override_until: Option<u64>,
+        profile: Option<String>,
+    }
+
+    impl ControllerState {
+        #[allow(dead_code)]
+        pub fn new(last_duty: Option<f64>) -> Self {
+            ControllerState {
+                last_duty,
+                ..Default::default()
+            }
+        }
+
+        pub fn get_last_duty(&self) -> Option<f64> {
+            self.last_duty
+        }
+
+        pub fn set_last_duty(&mut self, duty: f64) {
+            self.last_duty = Some(duty);
+        }
+
+        pub fn get_critical(&self) -> CriticalState {
+            let now = unix_now();
+            let age = |at: u64| Duration::from_secs(now.saturating_sub(at));
+            CriticalState {
+                above_for: self.critical_since.map(age),
+                fired_ago: self.critical_fired.map(age),
+                freq_limited: self.freq_limited,
+            }
+        }
+
+        pub fn set_critical(&mut self, critical: CriticalState) {
+            let now = unix_now();
+            let at = |age: Duration| now.saturating_sub(age.as_secs());
+            self.critical_since = critical.above_for.map(at);
+            self.critical_fired = critical.fired_ago.map(at);
+            self.freq_limited = critical.freq_limited;
+        }
+
+        pub fn get_settings(&self) -> ControllerSettings {
+            let now = unix_now();
+            let remaining = |until: u64| Duration::from_secs(until.saturating_sub(now));
+            ControllerSettings {
+                manual_speed_remaining: self.manual_speed_until.map(remaining),
+                manual_override: self
+                    .manual_override
+                    .map(|speed| (speed, self.manual_override_until.map(remaining))),
+                profile: self.profile.clone(),
+            }
+        }
+
+        pub fn set_settings(&mut self, settings: ControllerSettings) {
+            let now = unix_now();
+            let until = |remaining: Duration| now + remaining.as_secs();
+            self.manual_speed_until = settings.manual_speed_remaining.map(until);
+            self.manual_override = settings.manual_override.map(|(speed, _)| speed);
+            self.manual_override_until = settings
+                .manual_override
+                .and_then(|(_, remaining)| remaining)
+                .map(until);
+            self.profile = settings.profile;
+        }
+
+        /// Load the state, a missing, corrupted or older than `max_age` file gives the default
+        /// state.
+        pub fn load(path: &Path, max_age: Duration) -> Result<Self, io::Error> {
+            match fs::read_to_string(path) {
+                Ok(contents) => match Self::parse(&contents) {
+                    Ok(state) => {
+                        let age = state.saved_at.map(|at| unix_now().saturating_sub(at));
+                        if age.is_none_or(|age| age > max_age.as_secs()) {
+                            info!(
+                                "Ignoring state file {} saved more than {:?} ago",
+                                path.display(),
+                                max_age
+                            );
+                            return Ok(Self::default());
+                        }
+                        debug!("State loaded from {}: {:?}", path.display(), state);
+                        Ok(state)
+                    }
+                    Err(e) => {
+                        warn!("Ignoring state file {}: {}", path.display(), e);
+                        Ok(Self::default())
+                    }
+                },
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    debug!("No state file at {}", path.display());
+                    Ok(Self::default())
+                }
+                Err(e) => Err(e),
+            }
+        }
+
+        /// Save the state writing a temporary file renamed over the previous one,
+        /// so that a crash while saving never leaves a truncated state.
+        pub fn save(&mut self, path: &Path) -> Result<(), io::Error> {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            self.saved_at = Some(unix_now());
+
+            let mut tmp_path = path.as_os_str().to_owned();
+            tmp_path.push(".tmp");
+            fs::write(&tmp_path, self.serialize())?;
+            fs::rename(&tmp_path, path)?;
+            debug!("State saved to {}", path.display());
+            Ok(())
+        }
+
+        // one `key=value` per line, version first
+        fn serialize(&self) -> String {
+            let mut contents = format!("version={STATE_VERSION}\n");
+            if let Some(saved_at) = self.saved_at {
+                contents.push_str(&format!("saved_at={saved_at}\n"));
+            }
+            if let Some(duty) = self.last_duty {
+                contents.push_str(&format!("last_duty={duty}\n"));
+            }
+            if let Some(since) = self.critical_since {
+                contents.push_str(&format!("critical_since={since}\n"));
+            }
+            if let Some(fired) = self.critical_fired {
+                contents.push_str(&format!("critical_fired={fired}\n"));
+            }
+            if self.freq_limited {
+                contents.push_str("freq_limited=true\n");
+            }
+            if let Some(until) = self.manual_speed_until {
+                contents.push_str(&format!("manual_speed_until={until}\n"));
+            }
+            if let Some(speed) = self.manual_override {
+                contents.push_str(&format!("manual_override={speed}\n"));
+            }
+            if let Some(until) = self.manual_override_until {
+                contents.push_str(&format!("manual_override_until={until}\n"));
+            }
+            if let Some(profile) = &self.profile {
+                contents.push_str(&format!("profile={profile}\n"));
+            }
+            contents
+        }
+
+        fn parse(contents: &str) -> Result<Self, io::Error> {
+            let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+            let mut lines = contents.lines().map(str::trim).filter(|l| !l.is_empty());
+            match lines.next().and_then(|l| l.strip_prefix("version=")) {
+                Some(v) if v.parse::<u32>().ok() == Some(STATE_VERSION) => {}
+                Some(v) => return Err(invalid(format!("Unsupported state version `{v}`"))),
+                None => return Err(invalid("Missing state version".to_string())),
+            }
+
+            let mut state = Self::default();
+            for line in lines {
+                let (key, value) = line
+                    .split_once('=')
+                    .ok_or_else(|| invalid(format!("Invalid state line `{line}`")))?;
+                let timestamp = || {
+                    value
+                        .parse::<u64>()
+                        .map_err(|_| invalid(format!("Invalid state line `{line}`")))
+                };
+                match key {
+                    "saved_at" => state.saved_at = Some(timestamp()?),
+                    "critical_since" => state.critical_since = Some(timestamp()?),
+                    "critical_fired" => state.critical_fired = Some(timestamp()?),
+                    "manual_speed_until" => state.manual_speed_until = Some(timestamp()?),
+                    "manual_override_until" => state.manual_override_until = Some(timestamp()?),
+                    "manual_override" => {
+                        state.manual_override = Some(
+                            value
+                                .parse()
+                                .ok()
+                                .filter(|speed| *speed <= 100)
+                                .ok_or_else(|| invalid(format!("Invalid state line `{line}`")))?,
+                        );
+                    }
+                    "profile" => state.profile = Some(value.to_string()),
+                    "freq_limited" => {
+                        state.freq_limited = value
+                            .parse()
+                            .map_err(|_| invalid(format!("Invalid state line `{line}`")))?;
+                    }
+                    "last_duty" => {
+                        let duty: f64 = value
+                            .parse()
+                            .map_err(|_| invalid(format!("Invalid state line `{line}`")))?;
+                        if !(0.0..=1.0).contains(&duty) {
+                            return Err(invalid(format!("Duty out of range `{line}`")));
+                        }
+                        state.last_duty = Some(duty);
+                    }
+                    // written by a newer release of the same version, skip it
+                    _ => debug!("Unknown state key `{}`", key),
+                }
+            }
+            Ok(state)
+        }
+    }
+
+    fn unix_now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const MAX_AGE: Duration = Duration::from_secs(600);
+
+        #[test]
+        fn test_roundtrip() {
+            let mut state = ControllerState::new(Some(0.42));
+            assert_eq!(ControllerState::parse(&state.serialize()).unwrap(), state);
+
+            state.set_critical(CriticalState {
+                above_for: Some(Duration::from_secs(30)),
+                fired_ago: Some(Duration::from_secs(20)),
+                freq_limited: true,
+            });
+            let parsed = ControllerState::parse(&state.serialize()).unwrap();
+            assert_eq!(parsed, state);
+            assert!(parsed.get_critical().freq_limited);
+            assert!(parsed.get_critical().above_for >= Some(Duration::from_secs(30)));
+
+            state.set_settings(ControllerSettings {
+                manual_speed_remaining: Some(Duration::ZERO),
+                manual_override: Some((90, Some(Duration::from_secs(60)))),
+                profile: Some("silent".to_string()),
+            });
+            let parsed = ControllerState::parse(&state.serialize()).unwrap();
+            assert_eq!(parsed, state);
+            let settings = parsed.get_settings();
+            assert_eq!(settings.manual_speed_remaining, Some(Duration::ZERO));
+            assert!(settings.manual_override.unwrap().1 <= Some(Duration::from_secs(60)));
+            assert_eq!(settings.profile.as_deref(), Some("silent"));
+
+            let state = ControllerState::default();
+            assert_eq!(ControllerState::parse(&state.serialize()).unwrap(), state);
+        }
+
+        #[test]
+        fn test_parse_invalid() {
+            assert!(ControllerState::parse("version=1\nlast_duty=abc\n").is_err());
+            assert!(ControllerState::parse("version=1\ngarbage\n").is_err());
+            assert!(ControllerState::parse("version=1\nlast_duty=4.2\n").is_err());
+            assert!(ControllerState::parse("version=1\nmanual_override=101\n").is_err());
+        }
+
+        #[test]
+        fn test_parse_version() {
+            assert!(ControllerState::parse("last_duty=0.5\n").is_err());
+            assert!(ControllerState::parse("version=99\nlast_duty=0.5\n").is_err());
+            assert!(ControllerState::parse("").is_err());
+        }
+
+        #[test]
+        fn test_parse_unknown_key() {
+            let state = ControllerState::parse("version=1\nlast_duty=0.5\nfuture=1\n").unwrap();
+            assert_eq!(state.get_last_duty(), Some(0.5));
+        }
+
+        #[test]
+        fn test_load_missing_file() {
+            let path = std::env::temp_dir().join("rpi-temp-fan-pwm-missing.state");
+            assert_eq!(
+                ControllerState::load(&path, MAX_AGE).unwrap(),
+                ControllerState::default()
+            );
+        }
+
+        #[test]
+        fn test_save_load() {
+            let dir = std::env::temp_dir().join(format!("rpi-temp-fan-pwm-{}", std::process::id()));
+            let path = dir.join("state");
+            let mut state = ControllerState::new(Some(0.75));
+            state.save(&path).unwrap();
+            assert_eq!(ControllerState::load(&path, MAX_AGE).unwrap(), state);
+
+            // corrupted file falls back to the default state
+            fs::write(&path, "version=1\nlast_duty=\u{0}\u{0}").unwrap();
+            assert_eq!(
+                ControllerState::load(&path, MAX_AGE).unwrap(),
+                ControllerState::default()
+            );
+
+            // so does a state saved too long ago, or without its save time
+            let old = unix_now() - 601;
+            fs::write(&path, format!("version=1\nsaved_at={old}\nlast_duty=0.5\n")).unwrap();
+            assert_eq!(
+                ControllerState::load(&path, MAX_AGE).unwrap(),
+                ControllerState::default()
+            );
+            fs::write(&path, "version=1\nlast_duty=0.5\n").unwrap();
+            assert_eq!(
+                ControllerState::load(&path, MAX_AGE).unwrap(),
+                ControllerState::default()
+            );
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+pub mod svg_plot {
+    use crate::controller::fan_controller::FanMode;
+    use crate::history::history_recorder::HistoryRow;
+    use std::fmt::Write;
+
+    const WIDTH: f64 = 800.0;
+    const CHART_HEIGHT: f64 = 320.0;
+    const MARGIN_LEFT: f64 = 60.0;
+    const MARGIN_RIGHT: f64 = 20.0;
+    const MARGIN_TOP: f64 = 40.0;
+    const MARGIN_BOTTOM: f64 = 40.0;
+    const TICKS: usize = 5;
+    const COLORS: [&str; 6] = [
+        "#d62728", "#ff7f0e", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+    ];
+    const DUTY_COLOR: &str = "#1f77b4";
+    const APPLIED_COLOR: &str = "#2ca02c";
+
+    /// Value range of an axis with its tick labels.
+    struct Axis {
+        min: f64,
+        max: f64,
+        ticks: Vec<(f64, String)>,
+    }
+
+    impl Axis {
+        /// `TICKS` evenly spaced ticks labeled by `label`.
+        fn new(min: f64, max: f64, label: impl Fn(f64) -> String) -> Self {
+            // a flat series still gets a visible range
+            let max = if max > min { max } else { min + 1.0 };
+            let ticks = (0..TICKS)
+                .map(|i| {
+                    let value = min + (max - min) * i as f64 / (TICKS - 1) as f64;
+                    (value, label(value))
+                })
+                .collect();
+            Axis { min, max, ticks }
+        }
+
+        fn ratio(&self, value: f64) -> f64 {
+            (value - self.min) / (self.max - self.min)
+        }
+    }
+
+    struct Series<'a> {
+        name: &'a str,
+        color: &'a str,
+        points: Vec<(f64, f64)>,
+    }
+
+    /// Escape text content and attribute values.
+    fn escape(s: &str) -> String {
+        s.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    /// Chart with axes, grid, legend and one polyline per series, `top` pixels from the top.
+    fn chart(out: &mut String, top: f64, title: &str, x: &Axis, y: &Axis, series: &[Series]) {
+        let left = MARGIN_LEFT;
+        let right = WIDTH - MARGIN_RIGHT;
+        let plot_top = top + MARGIN_TOP;
+        let bottom = top + CHART_HEIGHT - MARGIN_BOTTOM;
+        let px = |v: f64| left + x.ratio(v) * (right - left);
+        let py = |v: f64| bottom - y.ratio(v) * (bottom - plot_top);
+
+        let _ = writeln!(
+            out,
+            r#"<text x="{}" y="{:.1}" font-size="16" text-anchor="middle">{}</text>"#,
+            WIDTH / 2.0,
+            top + 24.0,
+            escape(title)
+        );
+        for (value, label) in &y.ticks {
+            let _ = writeln!(
+                out,
+                r##"<line x1="{left}" y1="{0:.1}" x2="{right}" y2="{0:.1}" stroke="#ddd"/><text x="{1}" y="{0:.1}" font-size="12" text-anchor="end" dominant-baseline="middle">{2}</text>"##,
+                py(*value),
+                left - 6.0,
+                escape(label)
+            );
+        }
+        for (value, label) in &x.ticks {
+            let _ = writeln!(
+                out,
+                r##"<line x1="{0:.1}" y1="{plot_top:.1}" x2="{0:.1}" y2="{bottom:.1}" stroke="#ddd"/><text x="{0:.1}" y="{1:.1}" font-size="12" text-anchor="middle">{2}</text>"##,
+                px(*value),
+                bottom + 18.0,
+                escape(label)
+            );
+        }
+        let _ = writeln!(
+            out,
+            r#"<rect x="{left}" y="{plot_top:.1}" width="{:.1}" height="{:.1}" fill="none" stroke="black"/>"#,
+            right - left,
+            bottom - plot_top
+        );
+
+        for (i, s) in series.iter().enumerate() {
+            let points: Vec<String> = s
+                .points
+                .iter()
+                .map(|&(vx, vy)| format!("{:.1},{:.1}", px(vx), py(vy)))
+                .collect();
+            let _ = writeln!(
+                out,
+                r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="1.5"/>"#,
+                points.join(" "),
+                s.color
+            );
+            let legend_y = plot_top + 14.0 + 16.0 * i as f64;
+            let _ = writeln!(
+                out,
+                r#"<line x1="{0}" y1="{1:.1}" x2="{2}" y2="{1:.1}" stroke="{3}" stroke-width="3"/><text x="{4}" y="{1:.1}" font-size="12" dominant-baseline="middle">{5}</text>"#,
+                left + 10.0,
+                legend_y,
+                left + 30.0,
+                s.color,
+                left + 36.0,
+                escape(s.name)
+            );
+        }
+    }
+
+    fn document(charts: usize, body: &str) -> String {
+        let height = CHART_HEIGHT * charts as f64;
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{height}\" viewBox=\"0 0 {WIDTH} {height}\" font-family=\"sans-serif\">\n<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n{body}</svg>\n"
+        )
+    }
+
+    /// Chart of the duty cycle against the temperature, points from `duty_points`.
+    fn curve_chart(out: &mut String, top: f64, title: &str, points: &[(u8, f64, FanMode)]) {
+        let first = points.first().map_or(0.0, |p| f64::from(p.0));
+        let last = points.last().map_or(100.0, |p| f64::from(p.0));
+        let x = Axis::new(first, last, |v| format!("{v:.0}°C"));
+        let y = Axis::new(0.0, 100.0, |v| format!("{v:.0}%"));
+        let series = [Series {
+            name: "duty",
+            color: DUTY_COLOR,
+            points: points
+                .iter()
+                .map(|&(temp, duty, _)| (f64::from(temp), duty * 100.0))
+                .collect(),
+        }];
+        chart(out, top, title, &x, &y, &series);
+    }
+
+    /// Temperatures of each sensor, then target and applied duty cycle, over time.
+    fn history_charts(out: &mut String, top: f64, sensors: &[String], rows: &[HistoryRow]) {
+        let (Some(first), Some(last)) = (rows.first(), rows.last()) else {
+            return;
+        };
+        let start = first.get_timestamp();
+        let seconds =
+            |row: &HistoryRow| (row.get_timestamp() - start).num_milliseconds() as f64 / 1000.0;
+        let x = Axis::new(0.0, seconds(last), |v| {
+            (start + chrono::Duration::milliseconds((v * 1000.0) as i64))
+                .format("%m-%d %H:%M")
+                .to_string()
+        });
+
+        let temps = rows.iter().flat_map(|r| r.get_temps().iter().copied());
+        let min_temp = temps.clone().min().unwrap_or(0);
+        let max_temp = temps.max().unwrap_or(100);
+        let y = Axis::new(
+            f64::from(min_temp.saturating_sub(5)),
+            f64::from(max_temp.saturating_add(5)),
+            |v| format!("{v:.0}°C"),
+        );
+        let series: Vec<Series> = sensors
+            .iter()
+            .enumerate()
+            .map(|(i, sensor)| Series {
+                name: sensor,
+                color: COLORS[i % COLORS.len()],
+                points: rows
+                    .iter()
+                    .filter_map(|r| r.get_temps().get(i).map(|&t| (seconds(r), f64::from(t))))
+                    .collect(),
+            })
+            .collect();
+        chart(out, top, "Temperature", &x, &y, &series);
+
+        let y = Axis::new(0.0, 100.0, |v| format!("{v:.0}%"));
+        let series = [
+            Series {
+                name: "target duty",
+                color: DUTY_COLOR,
+                points: rows
+                    .iter()
+                    .map(|r| (seconds(r), r.get_target_duty() * 100.0))
+                    .collect(),
+            },
+            Series {
+                name: "applied duty",
+                color: APPLIED_COLOR,
+                points: rows
+                    .iter()
+                    .filter_map(|r| r.get_applied_duty().map(|d| (seconds(r), d * 100.0)))
+                    .collect(),
+            },
+        ];
+        chart(out, top + CHART_HEIGHT, "Duty cycle", &x, &y, &series);
+    }
+
+    /// SVG of the curve, followed by the charts of a recorded history when given.
+    pub fn render(
+        title: &str,
+        points: &[(u8, f64, FanMode)],
+        history: Option<(&[String], &[HistoryRow])>,
+    ) -> String {
+        let mut body = String::new();
+        curve_chart(&mut body, 0.0, title, points);
+        let charts = match history {
+            Some((sensors, rows)) if !rows.is_empty() => {
+                history_charts(&mut body, CHART_HEIGHT, sensors, rows);
+                3
+            }
+            _ => 1,
+        };
+        document(charts, &body)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::DateTime;
+
+        fn points() -> Vec<(u8, f64, FanMode)> {
+            vec![
+                (40, 0.2, FanMode::Auto),
+                (60, 0.5, FanMode::Auto),
+                (80, 1.0, FanMode::Auto),
+            ]
+        }
+
+        #[test]
+        fn test_escape() {
+            assert_eq!(escape("a<b> & \"c\""), "a&lt;b&gt; &amp; &quot;c&quot;");
+        }
+
+        #[test]
+        fn test_render_curve() {
+            let svg = render("cpu <fan>", &points(), None);
+            assert!(svg.starts_with(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"800\" height=\"320\""
+            ));
+            assert!(svg.ends_with("</svg>\n"));
+            assert!(svg.contains(">cpu &lt;fan&gt;</text>"));
+            // 40°C at the left edge and 20%, 80°C at the right edge and 100%
+            assert!(svg.contains(r#"<polyline points="60.0,232.0 420.0,160.0 780.0,40.0""#));
+            assert!(svg.contains(">60°C</text>"));
+        }
+
+        #[test]
+        fn test_render_history() {
+            let row = |time: &str, temp: u8, applied: Option<f64>| {
+                HistoryRow::new(
+                    DateTime::parse_from_rfc3339(time).unwrap(),
+                    vec![temp],
+                    0.5,
+                    applied,
+                    None,
+                    FanMode::Auto,
+                )
+            };
+            let rows = [
+                row("2026-06-01T12:00:00+02:00", 50, Some(0.5)),
+                row("2026-06-01T12:10:00+02:00", 60, None),
+                row("2026-06-01T12:20:00+02:00", 55, Some(0.5)),
+            ];
+            let sensors = ["cpu".to_string()];
+            let svg = render("curve", &points(), Some((&sensors, &rows)));
+
+            assert!(svg.contains("height=\"960\""));
+            assert_eq!(svg.matches("<polyline").count(), 4);
+            assert!(svg.contains(">Temperature</text>") && svg.contains(">Duty cycle</text>"));
+            assert!(svg.contains(">06-01 12:00</text>") && svg.contains(">06-01 12:20</text>"));
+            // the failed write is left out of the applied duty
+            let applied = svg.lines().rfind(|l| l.starts_with("<polyline")).unwrap();
+            assert_eq!(applied.matches(',').count(), 2);
+        }
+    }
+}
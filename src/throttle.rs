@@ -0,0 +1,264 @@
+pub mod throttle_status {
+    use log::{debug, info, warn};
+    use std::fs;
+    use std::io::{self, Read};
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Stdio};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Firmware file reporting the throttling flags, same value as `vcgencmd get_throttled`.
+    pub const THROTTLED_FILE: &str = "/sys/devices/platform/soc/soc:firmware/get_throttled";
+
+    /// `vcgencmd get_throttled` is killed after this long.
+    pub const VCGENCMD_TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// Interval of the background vcgencmd reads of the daemon.
+    pub const VCGENCMD_INTERVAL: Duration = Duration::from_secs(5);
+
+    /// Flags currently active, the same flags shifted by 16 bits report past occurrences.
+    pub const FLAGS: [(u32, &str); 4] = [
+        (1 << 0, "under_voltage"),
+        (1 << 1, "frequency_capped"),
+        (1 << 2, "throttled"),
+        (1 << 3, "soft_temp_limit"),
+    ];
+
+    /// Raspberry Pi firmware throttling and undervoltage flags.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct ThrottleFlags(u32);
+
+    impl ThrottleFlags {
+        pub fn new(bits: u32) -> Self {
+            ThrottleFlags(bits)
+        }
+
+        pub fn is_set(&self, flag: u32) -> bool {
+            self.0 & flag != 0
+        }
+
+        pub fn is_soft_temp_limit(&self) -> bool {
+            self.is_set(FLAGS[3].0)
+        }
+
+        /// Parse `50005` from sysfs or `throttled=0x50005` from vcgencmd.
+        pub fn parse(s: &str) -> Result<Self, io::Error> {
+            let value = s.trim();
+            let value = value.strip_prefix("throttled=").unwrap_or(value);
+            let value = value.strip_prefix("0x").unwrap_or(value);
+            u32::from_str_radix(value, 16)
+                .map(ThrottleFlags::new)
+                .map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid throttled value `{}`", s.trim()),
+                    )
+                })
+        }
+    }
+
+    /// Where the flags are read from, chosen once at startup.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum ThrottleSource {
+        File(PathBuf),
+        Vcgencmd,
+    }
+
+    impl ThrottleSource {
+        /// The firmware file when present, vcgencmd on a Raspberry Pi without it, none elsewhere.
+        pub fn detect(path: &Path, raspberry_pi: bool) -> Option<Self> {
+            if path.exists() {
+                Some(ThrottleSource::File(path.to_path_buf()))
+            } else if raspberry_pi {
+                Some(ThrottleSource::Vcgencmd)
+            } else {
+                None
+            }
+        }
+
+        pub fn read(&self) -> Result<ThrottleFlags, io::Error> {
+            match self {
+                ThrottleSource::File(path) => ThrottleFlags::parse(&fs::read_to_string(path)?),
+                ThrottleSource::Vcgencmd => {
+                    let mut child = Command::new("vcgencmd")
+                        .arg("get_throttled")
+                        .stdout(Stdio::piped())
+                        .stderr(Stdio::null())
+                        .spawn()?;
+                    let deadline = Instant::now() + VCGENCMD_TIMEOUT;
+                    let status = loop {
+                        if let Some(status) = child.try_wait()? {
+                            break status;
+                        }
+                        if Instant::now() >= deadline {
+                            let _ = child.kill();
+                            let _ = child.wait();
+                            return Err(io::Error::new(
+                                io::ErrorKind::TimedOut,
+                                "vcgencmd get_throttled timed out",
+                            ));
+                        }
+                        thread::sleep(Duration::from_millis(20));
+                    };
+                    if !status.success() {
+                        return Err(io::Error::other(format!(
+                            "vcgencmd get_throttled: {status}"
+                        )));
+                    }
+                    let mut stdout = String::new();
+                    if let Some(mut out) = child.stdout.take() {
+                        out.read_to_string(&mut stdout)?;
+                    }
+                    ThrottleFlags::parse(&stdout)
+                }
+            }
+        }
+    }
+
+    /// Reads the flags for the control loop. The file is read at every call, vcgencmd may run
+    /// for a while so the daemon reads it in a background thread and a call returns the latest
+    /// reading.
+    #[derive(Debug)]
+    pub struct ThrottleReader {
+        source: ThrottleSource,
+        latest: Option<Arc<Mutex<Result<ThrottleFlags, String>>>>,
+    }
+
+    impl ThrottleReader {
+        /// Read the source at every call.
+        pub fn direct(source: ThrottleSource) -> Self {
+            ThrottleReader {
+                source,
+                latest: None,
+            }
+        }
+
+        /// Read vcgencmd every `interval` in a thread stopping with the reader, the first reading
+        /// is done before returning.
+        pub fn background(source: ThrottleSource, interval: Duration) -> Self {
+            if !matches!(source, ThrottleSource::Vcgencmd) {
+                return Self::direct(source);
+            }
+            let latest = Arc::new(Mutex::new(source.read().map_err(|e| e.to_string())));
+            let weak = Arc::downgrade(&latest);
+            let thread_source = source.clone();
+            thread::spawn(move || loop {
+                thread::sleep(interval);
+                let flags = thread_source.read().map_err(|e| e.to_string());
+                match weak.upgrade() {
+                    Some(latest) => *latest.lock().unwrap() = flags,
+                    None => break,
+                }
+            });
+            ThrottleReader {
+                source,
+                latest: Some(latest),
+            }
+        }
+
+        pub fn read(&self) -> Result<ThrottleFlags, io::Error> {
+            match &self.latest {
+                Some(latest) => latest.lock().unwrap().clone().map_err(io::Error::other),
+                None => self.source.read(),
+            }
+        }
+    }
+
+    /// Logs the transitions of the current flags between two readings.
+    #[derive(Debug, Default)]
+    pub struct ThrottleMonitor {
+        last: ThrottleFlags,
+        failing: bool,
+    }
+
+    impl ThrottleMonitor {
+        /// Read the flags, a failure is logged once and the read retried at the next update.
+        pub fn read(&mut self, reader: &ThrottleReader) -> Option<ThrottleFlags> {
+            match reader.read() {
+                Ok(flags) => {
+                    if std::mem::take(&mut self.failing) {
+                        info!("Throttling flags readable again");
+                    }
+                    self.update(flags);
+                    Some(flags)
+                }
+                Err(e) if self.failing => {
+                    debug!("Error reading throttling flags: {}", e);
+                    None
+                }
+                Err(e) => {
+                    warn!("Error reading throttling flags, retrying: {}", e);
+                    self.failing = true;
+                    None
+                }
+            }
+        }
+
+        pub fn update(&mut self, flags: ThrottleFlags) {
+            for (flag, name) in FLAGS {
+                match (self.last.is_set(flag), flags.is_set(flag)) {
+                    (false, true) => warn!("Raspberry Pi {} detected", name),
+                    (true, false) => info!("Raspberry Pi {} cleared", name),
+                    _ => {}
+                }
+            }
+            self.last = flags;
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_parse() {
+            assert_eq!(ThrottleFlags::parse("0\n").unwrap(), ThrottleFlags::new(0));
+            assert_eq!(
+                ThrottleFlags::parse("50005\n").unwrap(),
+                ThrottleFlags::new(0x50005)
+            );
+            assert_eq!(
+                ThrottleFlags::parse("throttled=0x80008\n").unwrap(),
+                ThrottleFlags::new(0x80008)
+            );
+            assert!(ThrottleFlags::parse("throttled=").is_err());
+        }
+
+        #[test]
+        fn test_flags() {
+            let flags = ThrottleFlags::new(0x50005);
+            assert!(flags.is_set(FLAGS[0].0));
+            assert!(!flags.is_set(FLAGS[1].0));
+            assert!(flags.is_set(FLAGS[2].0));
+            assert!(!flags.is_soft_temp_limit());
+            assert!(ThrottleFlags::new(0x8).is_soft_temp_limit());
+        }
+
+        #[test]
+        fn test_read_file() {
+            let path = std::env::temp_dir()
+                .join(format!("rpi-temp-fan-pwm-throttled-{}", std::process::id()));
+            let _ = fs::remove_file(&path);
+            assert_eq!(ThrottleSource::detect(&path, false), None);
+            assert_eq!(
+                ThrottleSource::detect(&path, true),
+                Some(ThrottleSource::Vcgencmd)
+            );
+
+            fs::write(&path, "20002\n").unwrap();
+            let source = ThrottleSource::detect(&path, true).unwrap();
+            assert_eq!(source, ThrottleSource::File(path.clone()));
+            assert_eq!(source.read().unwrap(), ThrottleFlags::new(0x20002));
+
+            // a failed read doesn't stop the next ones, the file isn't read in the background
+            let reader = ThrottleReader::background(source, VCGENCMD_INTERVAL);
+            let mut monitor = ThrottleMonitor::default();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(monitor.read(&reader), None);
+            fs::write(&path, "8\n").unwrap();
+            assert_eq!(monitor.read(&reader), Some(ThrottleFlags::new(0x8)));
+            let _ = fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+pub mod trip_points {
+    use crate::curve::fan_curve::FanCurve;
+    use log::debug;
+    use std::fs;
+    use std::io::{self};
+    use std::path::Path;
+
+    /// Degrees before the passive trip point where the default curve reaches full speed.
+    pub const FULL_SPEED_MARGIN: u8 = 5;
+
+    /// Kind of a kernel thermal trip point, from `trip_point_N_type`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum TripType {
+        Active,
+        Passive,
+        Hot,
+        Critical,
+    }
+
+    /// Kernel thermal limit, in Celsius.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct TripPoint {
+        temp: u8,
+        kind: TripType,
+    }
+
+    impl TripPoint {
+        pub fn new(temp: u8, kind: TripType) -> Self {
+            TripPoint { temp, kind }
+        }
+
+        pub fn get_temp(&self) -> u8 {
+            self.temp
+        }
+    }
+
+    /// Read the trip points of a thermal zone directory, e.g. /sys/class/thermal/thermal_zone0 .
+    /// A directory without trip points gives an empty list.
+    pub fn read(zone_dir: &Path) -> Result<Vec<TripPoint>, io::Error> {
+        let mut trips = Vec::new();
+        for i in 0.. {
+            let temp_file = zone_dir.join(format!("trip_point_{i}_temp"));
+            let contents = match fs::read_to_string(&temp_file) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+                Err(e) => return Err(e),
+            };
+            let kind =
+                match fs::read_to_string(zone_dir.join(format!("trip_point_{i}_type")))?.trim() {
+                    "active" => TripType::Active,
+                    "passive" => TripType::Passive,
+                    "hot" => TripType::Hot,
+                    "critical" => TripType::Critical,
+                    other => {
+                        debug!("Unknown trip point type `{}`", other);
+                        continue;
+                    }
+                };
+            // millidegree Celsius, negative or disabled trip points are skipped
+            match contents.trim().parse::<i64>() {
+                Ok(millis) if millis > 0 => {
+                    trips.push(TripPoint::new((millis / 1000).min(255) as u8, kind))
+                }
+                _ => debug!("Ignoring trip point {}: `{}`", i, contents.trim()),
+            }
+        }
+        Ok(trips)
+    }
+
+    /// Temperature at which the kernel starts throttling: the lowest passive trip point,
+    /// or the critical one when there's no passive trip.
+    pub fn throttle_temp(trips: &[TripPoint]) -> Option<u8> {
+        let lowest = |kind| {
+            trips
+                .iter()
+                .filter(|t| t.kind == kind)
+                .map(TripPoint::get_temp)
+                .min()
+        };
+        lowest(TripType::Passive).or_else(|| lowest(TripType::Critical))
+    }
+
+    /// Curve reaching full speed `FULL_SPEED_MARGIN` degrees before the kernel throttles.
+    pub fn default_curve(trips: &[TripPoint]) -> Option<FanCurve> {
+        let full = throttle_temp(trips)?.saturating_sub(FULL_SPEED_MARGIN);
+        if full < 20 {
+            return None;
+        }
+        Some(FanCurve::new(
+            vec![full - 20, full - 10, full],
+            vec![20, 50, 100],
+        ))
+    }
+
+    /// Temperature at which the curve reaches full speed, none if it never does.
+    pub fn full_speed_temp(curve: &FanCurve) -> Option<u8> {
+        curve
+            .get_speed_step()
+            .iter()
+            .position(|&speed| speed >= 100)
+            .map(|i| curve.get_temp_step()[i])
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn zone(trips: &[(&str, &str)]) -> std::path::PathBuf {
+            let dir = std::env::temp_dir().join(format!(
+                "rpi-temp-fan-pwm-zone-{}-{}",
+                std::process::id(),
+                trips.len()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            for (i, (temp, kind)) in trips.iter().enumerate() {
+                fs::write(dir.join(format!("trip_point_{i}_temp")), temp).unwrap();
+                fs::write(dir.join(format!("trip_point_{i}_type")), kind).unwrap();
+            }
+            dir
+        }
+
+        #[test]
+        fn test_read() {
+            let dir = zone(&[("80000\n", "passive\n"), ("110000\n", "critical\n")]);
+            assert_eq!(
+                read(&dir).unwrap(),
+                vec![
+                    TripPoint::new(80, TripType::Passive),
+                    TripPoint::new(110, TripType::Critical)
+                ]
+            );
+            let _ = fs::remove_dir_all(dir);
+
+            let dir = zone(&[]);
+            assert!(read(&dir).unwrap().is_empty());
+            let _ = fs::remove_dir_all(dir);
+        }
+
+        #[test]
+        fn test_default_curve() {
+            let trips = [
+                TripPoint::new(110, TripType::Critical),
+                TripPoint::new(85, TripType::Passive),
+                TripPoint::new(80, TripType::Passive),
+            ];
+            assert_eq!(throttle_temp(&trips), Some(80));
+            assert_eq!(
+                default_curve(&trips),
+                Some(FanCurve::new(vec![55, 65, 75], vec![20, 50, 100]))
+            );
+
+            let trips = [TripPoint::new(90, TripType::Critical)];
+            assert_eq!(throttle_temp(&trips), Some(90));
+            assert_eq!(default_curve(&[]), None);
+        }
+
+        #[test]
+        fn test_full_speed_temp() {
+            let curve = FanCurve::new(vec![50, 70, 80, 90], vec![20, 50, 100, 100]);
+            assert_eq!(full_speed_temp(&curve), Some(80));
+            let curve = FanCurve::new(vec![50, 70], vec![20, 90]);
+            assert_eq!(full_speed_temp(&curve), None);
+        }
+    }
+}
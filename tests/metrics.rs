@@ -0,0 +1,128 @@
+//! Scrape the metrics endpoint of a daemon started with `--dry-run`, reading a fake sensor.
+#![cfg(feature = "prometheus")]
+
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{self, Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Daemon process, killed when the test ends.
+struct Daemon(Child);
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn temp_dir() -> PathBuf {
+    let dir = env::temp_dir().join(format!("rpi-temp-fan-pwm-metrics-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn scrape(port: u16) -> Option<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).ok()?;
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .ok()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+    Some(response)
+}
+
+/// Scrape until the metrics contain every line of `expected`.
+fn wait_for(port: u16, expected: &[&str]) -> String {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let response = scrape(port);
+        if let Some(response) = &response {
+            if expected.iter().all(|line| response.contains(line)) {
+                return response.clone();
+            }
+        }
+        assert!(
+            Instant::now() < deadline,
+            "expected {expected:?}, scraped {response:?}"
+        );
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn test_scrape_daemon() {
+    let dir = temp_dir();
+    let sensor = dir.join("temp");
+    fs::write(&sensor, "60000\n").unwrap();
+    let port = free_port();
+
+    let _daemon = Daemon(
+        Command::new(env!("CARGO_BIN_EXE_rpi-temp-fan-pwm"))
+            .arg("--dry-run")
+            .arg(format!(
+                "--control-socket={}",
+                dir.join("control.sock").display()
+            ))
+            .arg(format!("--state-file={}", dir.join("state").display()))
+            .arg(format!(
+                "--throttled-file={}",
+                dir.join("throttled").display()
+            ))
+            .arg("--interval=100ms")
+            .arg(format!("--curve={}:50,70:20,100", sensor.display()))
+            .arg(format!("--metrics-listen=127.0.0.1:{port}"))
+            .env_remove("JOURNAL_STREAM")
+            .env_remove("NOTIFY_SOCKET")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap(),
+    );
+
+    let response = wait_for(
+        port,
+        &[
+            &format!(
+                "rpi_fan_temperature_celsius{{sensor=\"{}\"}} 60\n",
+                sensor.display()
+            ),
+            "rpi_fan_target_duty_ratio 0.6\n",
+            "rpi_fan_applied_duty_ratio 0.6\n",
+        ],
+    );
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("rpi_fan_sensor_read_errors_total 0\n"));
+    assert!(response.contains("rpi_fan_loop_duration_seconds "));
+
+    // failsafe: full speed while the sensor is missing, the target keeps the last reading
+    fs::remove_file(&sensor).unwrap();
+    let response = wait_for(port, &["rpi_fan_applied_duty_ratio 1\n"]);
+    assert!(response.contains("rpi_fan_target_duty_ratio 0.6\n"));
+    assert!(!response.contains("rpi_fan_sensor_read_errors_total 0\n"));
+
+    // back to the curve at the next reading
+    fs::write(&sensor, "65000\n").unwrap();
+    wait_for(
+        port,
+        &[
+            "rpi_fan_target_duty_ratio 0.8\n",
+            "rpi_fan_applied_duty_ratio 0.8\n",
+        ],
+    );
+
+    let _ = fs::remove_dir_all(&dir);
+}